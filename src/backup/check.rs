@@ -0,0 +1,66 @@
+use serde::Serialize;
+
+use crate::error::Result;
+use crate::storage::Database;
+use crate::utils::ContentHasher;
+
+/// Verifies that a repository is consistent and that all stored content matches its hash.
+pub struct Check {
+    db: Database,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CheckReport {
+    pub snapshots: u64,
+    pub files: u64,
+    pub blocks: u64,
+    pub bytes_read: u64,
+    pub problems: Vec<String>,
+}
+
+impl Check {
+    pub fn new(db: Database) -> Self {
+        Check { db }
+    }
+
+    pub fn run(&self) -> Result<CheckReport> {
+        verify(&self.db)
+    }
+}
+
+/// Checks the database structure, then reads every content block back and hashes it.
+/// Problems are collected in the report rather than returned as errors.
+pub(crate) fn verify(db: &Database) -> Result<CheckReport> {
+    db.flush_packs()?;
+    let mut report = CheckReport {
+        snapshots: 0,
+        files: 0,
+        blocks: 0,
+        bytes_read: 0,
+        problems: db.structural_problems()?,
+    };
+
+    for snapshot in db.get_snapshots()? {
+        report.snapshots += 1;
+        report.files += db.get_snapshot_files(snapshot.id)?.len() as u64;
+    }
+
+    for hash in db.get_content_hashes()? {
+        let mut hasher = ContentHasher::default();
+        let mut size = 0;
+        let read = db.read_file_content(&hash, |piece| {
+            hasher.update(piece);
+            size += piece.len() as u64;
+            Ok(())
+        });
+        match read {
+            Ok(()) if hasher.finish() == hash => {}
+            Ok(()) => report.problems.push(format!("content block {} does not match its hash", hash)),
+            Err(e) => report.problems.push(format!("content block {}: {}", hash, e.describe())),
+        }
+        report.blocks += 1;
+        report.bytes_read += size;
+    }
+
+    Ok(report)
+}
@@ -0,0 +1,140 @@
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+use crate::storage::Database;
+use crate::storage::database::SnapshotRecord;
+use crate::utils::hash_content;
+use super::check_foreign_path;
+
+/// Copies snapshots from one repository into another.
+pub struct Transfer {
+    source: Database,
+    destination: Database,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CopiedSnapshot {
+    pub uuid: String,
+    pub source_id: u32,
+    pub destination_id: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CopyReport {
+    pub copied: Vec<CopiedSnapshot>,
+    /// Source snapshots the destination already had.
+    pub skipped: Vec<u32>,
+    pub blocks_copied: u64,
+    pub bytes_copied: u64,
+}
+
+impl Transfer {
+    pub fn new(source: Database, destination: Database) -> Self {
+        Transfer { source, destination }
+    }
+
+    /// Copies the given source snapshots, or all of them when `snapshot_ids` is empty.
+    /// Snapshots are matched by UUID, so copying again only transfers new ones, and only
+    /// content blocks the destination lacks are transferred.
+    pub fn copy(&self, snapshot_ids: &[u32]) -> Result<CopyReport> {
+        if self.source.repository_info()?.repository_id == self.destination.repository_info()?.repository_id {
+            return Err(Error::InvalidInput("source and destination are the same repository".to_string()));
+        }
+        let snapshots: Vec<SnapshotRecord> = self.source.get_snapshots()?
+            .into_iter()
+            .filter(|s| snapshot_ids.is_empty() || snapshot_ids.contains(&s.id))
+            .collect();
+        self.destination.transaction(|| self.copy_snapshots(&snapshots))
+    }
+
+    fn copy_snapshots(&self, snapshots: &[SnapshotRecord]) -> Result<CopyReport> {
+        let mut copier = Copier::new(&self.destination);
+        let mut copied = Vec::new();
+        let mut skipped = Vec::new();
+
+        for snapshot in snapshots {
+            if self.destination.find_snapshot_by_uuid(&snapshot.uuid)?.is_some() {
+                skipped.push(snapshot.id);
+                continue;
+            }
+            copied.push(CopiedSnapshot {
+                uuid: snapshot.uuid.clone(),
+                source_id: snapshot.id,
+                destination_id: copier.copy_snapshot(&self.source, snapshot)?,
+            });
+        }
+
+        Ok(CopyReport { copied, skipped, blocks_copied: copier.blocks_copied, bytes_copied: copier.bytes_copied })
+    }
+}
+
+/// Writes snapshots from other repositories into `destination`, counting the content it
+/// had to transfer. Callers run it inside a transaction on `destination`.
+pub(crate) struct Copier<'a> {
+    destination: &'a Database,
+    pub blocks_copied: u64,
+    pub bytes_copied: u64,
+}
+
+impl<'a> Copier<'a> {
+    pub fn new(destination: &'a Database) -> Self {
+        Copier { destination, blocks_copied: 0, bytes_copied: 0 }
+    }
+
+    /// Copies one snapshot of `source` with its UUID, tags and files, and returns its new id.
+    pub fn copy_snapshot(&mut self, source: &Database, snapshot: &SnapshotRecord) -> Result<u32> {
+        let files = source.get_snapshot_files(snapshot.id)?;
+        for file in &files {
+            check_foreign_path(&file.path)?;
+        }
+        let snapshot_id = self.destination.insert_snapshot_with_uuid(
+            &snapshot.timestamp, &snapshot.target_directory, &snapshot.uuid
+        )?;
+        self.destination.set_snapshot_message(snapshot_id, snapshot.message.as_deref())?;
+        self.destination.set_snapshot_protected(snapshot_id, snapshot.protected)?;
+        for snapshot_source in &snapshot.sources {
+            self.destination.add_snapshot_source(snapshot_id, snapshot_source)?;
+        }
+        for tag in &snapshot.tags {
+            self.destination.add_snapshot_tag(snapshot_id, tag)?;
+        }
+        for (path, error) in source.get_snapshot_errors(snapshot.id)? {
+            self.destination.record_snapshot_error(snapshot_id, &path, &error)?;
+        }
+        for file in files {
+            self.copy_content(source, &file.content_hash, file.size)?;
+            self.destination.add_file_with_metadata(
+                snapshot_id, &file.path, &file.content_hash, file.size, file.mode, file.mtime
+            )?;
+        }
+
+        Ok(snapshot_id)
+    }
+
+    /// Copies a file's content unless the destination has it, chunk by chunk for chunked content.
+    fn copy_content(&mut self, source: &Database, hash: &str, size: u64) -> Result<()> {
+        if self.destination.content_exists(hash)? {
+            return Ok(());
+        }
+
+        let chunks = source.get_content_chunks(hash)?;
+        let blocks = if chunks.is_empty() { vec![hash.to_string()] } else { chunks.clone() };
+        for block in &blocks {
+            if self.destination.content_exists(block)? {
+                continue;
+            }
+            let content = source.get_file_content(block)?;
+            if hash_content(&content) != *block {
+                return Err(Error::Corruption(format!("content block {} does not match its hash", block)));
+            }
+            self.destination.store_content(block, &content)?;
+            self.blocks_copied += 1;
+            self.bytes_copied += content.len() as u64;
+        }
+        if !chunks.is_empty() {
+            self.destination.store_chunked_content(hash, size, &chunks)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,174 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, Seek, Write};
+use std::path::Path;
+use std::str::FromStr;
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+use crate::storage::Database;
+use crate::storage::database::{FileInfo, SnapshotRecord};
+
+/// Mode given to exported files whose snapshot did not record permissions.
+const DEFAULT_MODE: u32 = 0o644;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum ExportFormat {
+    #[serde(rename = "tar")]
+    Tar,
+    #[serde(rename = "tar.zst")]
+    TarZst,
+    #[serde(rename = "zip")]
+    Zip,
+}
+
+impl FromStr for ExportFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "tar" => Ok(ExportFormat::Tar),
+            "tar.zst" => Ok(ExportFormat::TarZst),
+            "zip" => Ok(ExportFormat::Zip),
+            _ => Err(Error::InvalidInput(format!("unknown archive format {:?} (expected tar, tar.zst or zip)", s))),
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ExportFormat::Tar => "tar",
+            ExportFormat::TarZst => "tar.zst",
+            ExportFormat::Zip => "zip",
+        })
+    }
+}
+
+pub struct Export {
+    db: Database,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExportReport {
+    pub snapshot_id: u32,
+    pub format: ExportFormat,
+    pub output: String,
+    pub files_exported: u64,
+    pub total_size: u64,
+}
+
+impl Export {
+    pub fn new(db: Database) -> Self {
+        Export { db }
+    }
+
+    /// Writes the snapshot as an archive at `output`, or to standard output if it is `-`.
+    /// A partially written file is removed if the export fails.
+    pub fn export_to_path(&self, snapshot_id: u32, format: ExportFormat, output: &Path) -> Result<ExportReport> {
+        if output == Path::new("-") {
+            return self.export_to_writer(snapshot_id, format, io::stdout().lock(), output);
+        }
+
+        let snapshot = self.db.get_snapshot(snapshot_id)?;
+        let file = fs::File::create(output).map_err(|e| Error::io(output, e))?;
+        let result = match format {
+            ExportFormat::Zip => self.write_zip(&snapshot, file, output),
+            _ => self.write_tar(&snapshot, format, file, output),
+        };
+        if result.is_err() {
+            let _ = fs::remove_file(output);
+        }
+
+        let (files_exported, total_size) = result?;
+        Ok(ExportReport { snapshot_id, format, output: output.display().to_string(), files_exported, total_size })
+    }
+
+    /// Streams the snapshot as a tar archive into `writer`. Zip archives need a seekable
+    /// output, so use `export_to_path` for them.
+    pub fn export_to_writer(&self, snapshot_id: u32, format: ExportFormat, writer: impl Write, name: &Path) -> Result<ExportReport> {
+        if format == ExportFormat::Zip {
+            return Err(Error::InvalidInput("zip archives cannot be streamed; write them to a file or use tar".to_string()));
+        }
+
+        let snapshot = self.db.get_snapshot(snapshot_id)?;
+        let (files_exported, total_size) = self.write_tar(&snapshot, format, writer, name)?;
+        Ok(ExportReport { snapshot_id, format, output: name.display().to_string(), files_exported, total_size })
+    }
+
+    fn files(&self, snapshot_id: u32) -> Result<Vec<FileInfo>> {
+        let mut files = self.db.get_snapshot_files(snapshot_id)?;
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(files)
+    }
+
+    fn write_tar(&self, snapshot: &SnapshotRecord, format: ExportFormat, writer: impl Write, name: &Path) -> Result<(u64, u64)> {
+        let io_error = |e| Error::io(name, e);
+        let files = self.files(snapshot.id)?;
+        match format {
+            ExportFormat::TarZst => {
+                let encoder = zstd::Encoder::new(writer, 0).map_err(io_error)?;
+                let encoder = self.append_tar_entries(snapshot, &files, encoder, name)?;
+                let mut writer = encoder.finish().map_err(io_error)?;
+                writer.flush().map_err(io_error)?;
+            }
+            _ => {
+                let mut writer = self.append_tar_entries(snapshot, &files, writer, name)?;
+                writer.flush().map_err(io_error)?;
+            }
+        }
+
+        Ok((files.len() as u64, files.iter().map(|f| f.size).sum()))
+    }
+
+    fn append_tar_entries<W: Write>(&self, snapshot: &SnapshotRecord, files: &[FileInfo], writer: W, name: &Path) -> Result<W> {
+        let mut builder = tar::Builder::new(writer);
+        for file in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Regular);
+            header.set_size(file.size);
+            header.set_mode(file.mode.unwrap_or(DEFAULT_MODE));
+            header.set_mtime(file.mtime.unwrap_or(snapshot.timestamp.timestamp()).max(0) as u64);
+
+            let content = self.db.content_reader(&file.content_hash)?;
+            builder.append_data(&mut header, &file.path, content)
+                .map_err(|e| Error::io(name, e))?;
+        }
+
+        builder.into_inner().map_err(|e| Error::io(name, e))
+    }
+
+    fn write_zip(&self, snapshot: &SnapshotRecord, writer: impl Write + Seek, name: &Path) -> Result<(u64, u64)> {
+        let zip_error = |e: zip::result::ZipError| Error::io(name, e.into());
+        let mut zip = zip::ZipWriter::new(writer);
+        let files = self.files(snapshot.id)?;
+        for file in &files {
+            let options = zip::write::SimpleFileOptions::default()
+                .compression_method(zip::CompressionMethod::Deflated)
+                .last_modified_time(zip_time(file.mtime, snapshot))
+                .unix_permissions(file.mode.unwrap_or(DEFAULT_MODE))
+                .large_file(file.size >= u32::MAX as u64);
+            zip.start_file(file.path.as_str(), options).map_err(zip_error)?;
+
+            let mut content = self.db.content_reader(&file.content_hash)?;
+            io::copy(&mut content, &mut zip).map_err(|e| Error::io(name, e))?;
+        }
+        zip.finish().map_err(zip_error)?;
+
+        Ok((files.len() as u64, files.iter().map(|f| f.size).sum()))
+    }
+}
+
+/// The file's recorded mtime, or the snapshot time, clamped to the range zip can store.
+fn zip_time(mtime: Option<i64>, snapshot: &SnapshotRecord) -> zip::DateTime {
+    let timestamp = mtime.and_then(|t| DateTime::<Utc>::from_timestamp(t, 0)).unwrap_or(snapshot.timestamp);
+    zip::DateTime::from_date_and_time(
+        timestamp.year().clamp(1980, 2107) as u16,
+        timestamp.month() as u8,
+        timestamp.day() as u8,
+        timestamp.hour() as u8,
+        timestamp.minute() as u8,
+        timestamp.second().min(58) as u8,
+    ).unwrap_or_default()
+}
@@ -0,0 +1,97 @@
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+use crate::storage::{Database, SnapshotFilter};
+use super::check::{verify, CheckReport};
+use super::copy::Copier;
+
+/// Builds a new repository out of the snapshots of others. Merging combines whole
+/// repositories; splitting takes the snapshots of one directory out of a repository.
+pub struct Merge {
+    output: Database,
+    inputs: Vec<(String, Database)>,
+    target_directory: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MergedSnapshot {
+    /// Name or path of the input repository the snapshot came from.
+    pub repository: String,
+    pub source_id: u32,
+    /// Id of the snapshot in the new repository.
+    pub snapshot_id: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MergeReport {
+    pub snapshots: Vec<MergedSnapshot>,
+    /// Snapshots an earlier input already contributed, e.g. because they were copied between
+    /// the inputs; `snapshot_id` is the one they were merged into.
+    pub duplicates: Vec<MergedSnapshot>,
+    pub blocks_copied: u64,
+    pub bytes_copied: u64,
+    /// Integrity check of the new repository.
+    pub check: CheckReport,
+}
+
+impl Merge {
+    /// `output` should be a freshly initialised repository.
+    pub fn new(output: Database) -> Self {
+        Merge { output, inputs: Vec::new(), target_directory: None }
+    }
+
+    pub fn with_input(mut self, name: impl Into<String>, db: Database) -> Self {
+        self.inputs.push((name.into(), db));
+        self
+    }
+
+    /// Only take snapshots one of whose sources is this directory.
+    pub fn only_target_directory(mut self, target_directory: Option<String>) -> Self {
+        self.target_directory = target_directory;
+        self
+    }
+
+    /// Copies the selected snapshots into the output, numbering them in the order they were
+    /// taken, then checks the output's integrity.
+    pub fn run(&self) -> Result<MergeReport> {
+        let filter = self.target_directory.clone().map(SnapshotFilter::Path);
+        let mut selected = Vec::new();
+        for (index, (_, db)) in self.inputs.iter().enumerate() {
+            for snapshot in db.get_snapshots()? {
+                if filter.as_ref().is_none_or(|filter| filter.matches(&snapshot)) {
+                    selected.push((index, snapshot));
+                }
+            }
+        }
+        if selected.is_empty() {
+            return Err(Error::InvalidInput(match &self.target_directory {
+                Some(dir) => format!("no snapshot includes {}", dir),
+                None => "the repositories have no snapshots".to_string(),
+            }));
+        }
+        selected.sort_by_key(|(index, snapshot)| (snapshot.timestamp, *index, snapshot.id));
+
+        let mut snapshots = Vec::new();
+        let mut duplicates = Vec::new();
+        let mut copier = Copier::new(&self.output);
+        self.output.transaction(|| {
+            for (index, snapshot) in &selected {
+                let (name, source) = &self.inputs[*index];
+                let (snapshot_id, list) = match self.output.find_snapshot_by_uuid(&snapshot.uuid)? {
+                    Some(snapshot_id) => (snapshot_id, &mut duplicates),
+                    None => (copier.copy_snapshot(source, snapshot)?, &mut snapshots),
+                };
+                list.push(MergedSnapshot { repository: name.clone(), source_id: snapshot.id, snapshot_id });
+            }
+            Ok(())
+        })?;
+
+        Ok(MergeReport {
+            snapshots,
+            duplicates,
+            blocks_copied: copier.blocks_copied,
+            bytes_copied: copier.bytes_copied,
+            check: verify(&self.output)?,
+        })
+    }
+}
@@ -1,7 +1,49 @@
 pub mod snapshot;
 pub mod restore;
 pub mod prune;
+pub mod remote;
+pub mod export;
+pub mod copy;
+pub mod merge;
+pub mod check;
+pub mod stats;
+pub mod progress;
 
-pub use snapshot::Snapshot;
-pub use restore::Restore;
-pub use prune::Prune;
\ No newline at end of file
+use std::path::{Component, Path};
+use serde::{Serialize, Deserialize};
+
+use crate::error::{Error, Result};
+
+pub use snapshot::{Snapshot, SnapshotSummary};
+pub use restore::{Restore, RestoreReport};
+pub use prune::{Prune, PruneScope, PruneSelection, PruneReport, RetentionReport, ForgetReport, GcReport};
+pub use remote::{Remote, PushReport, PullReport};
+pub use export::{Export, ExportFormat, ExportReport};
+pub use copy::{Transfer, CopyReport, CopiedSnapshot};
+pub use merge::{Merge, MergeReport, MergedSnapshot};
+pub use check::{Check, CheckReport};
+pub use stats::{Stats, StatsReport};
+pub use progress::{Progress, NoProgress, TerminalProgress, LogProgress};
+
+/// A file that could not be processed, with the reason.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileError {
+    pub path: String,
+    pub error: String,
+}
+
+impl FileError {
+    pub fn new(path: impl Into<String>, error: &Error) -> Self {
+        FileError { path: path.into(), error: error.describe() }
+    }
+}
+
+/// Refuses a file path read from a remote index or another repository unless it is made of
+/// normal relative components, so that restoring it cannot write outside the output directory.
+pub(crate) fn check_foreign_path(path: &str) -> Result<()> {
+    let relative = !path.is_empty() && Path::new(path).components().all(|c| matches!(c, Component::Normal(_)));
+    if !relative {
+        return Err(Error::Corruption(format!("file path {:?} escapes the snapshot root", path)));
+    }
+    Ok(())
+}
@@ -0,0 +1,219 @@
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+/// Observer for long-running operations. All methods default to doing nothing.
+pub trait Progress {
+    /// Called once before any file is processed, with the expected totals.
+    fn start(&self, _total_files: u64, _total_bytes: u64) {}
+    fn file_started(&self, _path: &str) {}
+    fn bytes_processed(&self, _bytes: u64) {}
+    fn file_finished(&self, _path: &str) {}
+    fn finish(&self) {}
+}
+
+/// Progress observer that ignores every event.
+pub struct NoProgress;
+
+impl Progress for NoProgress {}
+
+struct Counters {
+    total_files: u64,
+    total_bytes: u64,
+    files_done: u64,
+    bytes_done: u64,
+    started: Instant,
+    last_report: Option<Instant>,
+}
+
+impl Counters {
+    fn new() -> Self {
+        Counters {
+            total_files: 0,
+            total_bytes: 0,
+            files_done: 0,
+            bytes_done: 0,
+            started: Instant::now(),
+            last_report: None,
+        }
+    }
+
+    fn due(&mut self, interval: Duration) -> bool {
+        let now = Instant::now();
+        match self.last_report {
+            Some(last) if now.duration_since(last) < interval => false,
+            _ => {
+                self.last_report = Some(now);
+                true
+            }
+        }
+    }
+
+    fn fraction(&self) -> f64 {
+        if self.total_bytes > 0 {
+            (self.bytes_done as f64 / self.total_bytes as f64).min(1.0)
+        } else if self.total_files > 0 {
+            (self.files_done as f64 / self.total_files as f64).min(1.0)
+        } else {
+            1.0
+        }
+    }
+
+    fn throughput(&self) -> f64 {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        if elapsed > 0.0 { self.bytes_done as f64 / elapsed } else { 0.0 }
+    }
+
+    fn eta(&self) -> Option<Duration> {
+        let rate = self.throughput();
+        if rate <= 0.0 || self.bytes_done >= self.total_bytes {
+            return None;
+        }
+        Some(Duration::from_secs_f64((self.total_bytes - self.bytes_done) as f64 / rate))
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "{}/{} files, {}/{} ({:.0}%), {}/s, ETA {}",
+            self.files_done,
+            self.total_files,
+            format_bytes(self.bytes_done),
+            format_bytes(self.total_bytes),
+            self.fraction() * 100.0,
+            format_bytes(self.throughput() as u64),
+            self.eta().map(format_duration).unwrap_or_else(|| "--:--".to_string()),
+        )
+    }
+}
+
+/// Single-line progress bar redrawn in place on stderr, for interactive terminals.
+pub struct TerminalProgress {
+    counters: RefCell<Counters>,
+}
+
+const BAR_WIDTH: usize = 30;
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+impl TerminalProgress {
+    pub fn new() -> Self {
+        TerminalProgress { counters: RefCell::new(Counters::new()) }
+    }
+
+    fn draw(&self, force: bool) {
+        let mut counters = self.counters.borrow_mut();
+        if !counters.due(REDRAW_INTERVAL) && !force {
+            return;
+        }
+
+        let filled = (counters.fraction() * BAR_WIDTH as f64) as usize;
+        let bar = format!("{}{}", "=".repeat(filled), " ".repeat(BAR_WIDTH - filled));
+        let mut stderr = io::stderr();
+        let _ = write!(stderr, "\r\x1b[2K[{}] {}", bar, counters.describe());
+        let _ = stderr.flush();
+    }
+}
+
+impl Default for TerminalProgress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Progress for TerminalProgress {
+    fn start(&self, total_files: u64, total_bytes: u64) {
+        let mut counters = self.counters.borrow_mut();
+        counters.total_files = total_files;
+        counters.total_bytes = total_bytes;
+        counters.started = Instant::now();
+    }
+
+    fn bytes_processed(&self, bytes: u64) {
+        self.counters.borrow_mut().bytes_done += bytes;
+        self.draw(false);
+    }
+
+    fn file_finished(&self, _path: &str) {
+        self.counters.borrow_mut().files_done += 1;
+        self.draw(false);
+    }
+
+    fn finish(&self) {
+        self.draw(true);
+        eprintln!();
+    }
+}
+
+/// Periodic plain-text progress lines on stderr, suitable for log files.
+pub struct LogProgress {
+    counters: RefCell<Counters>,
+    interval: Duration,
+}
+
+impl LogProgress {
+    pub fn new(interval: Duration) -> Self {
+        LogProgress { counters: RefCell::new(Counters::new()), interval }
+    }
+
+    fn report(&self) {
+        let mut counters = self.counters.borrow_mut();
+        let interval = self.interval;
+        if counters.due(interval) {
+            eprintln!("Progress: {}", counters.describe());
+        }
+    }
+}
+
+impl Progress for LogProgress {
+    fn start(&self, total_files: u64, total_bytes: u64) {
+        let mut counters = self.counters.borrow_mut();
+        counters.total_files = total_files;
+        counters.total_bytes = total_bytes;
+        counters.started = Instant::now();
+        // The first report is due one interval after starting
+        counters.last_report = Some(counters.started);
+    }
+
+    fn bytes_processed(&self, bytes: u64) {
+        self.counters.borrow_mut().bytes_done += bytes;
+        self.report();
+    }
+
+    fn file_finished(&self, _path: &str) {
+        self.counters.borrow_mut().files_done += 1;
+        self.report();
+    }
+
+    fn finish(&self) {
+        let counters = self.counters.borrow();
+        eprintln!(
+            "Progress: done, {} files, {} in {}",
+            counters.files_done,
+            format_bytes(counters.bytes_done),
+            format_duration(counters.started.elapsed()),
+        );
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, (secs / 60) % 60, secs % 60)
+    } else {
+        format!("{:02}:{:02}", secs / 60, secs % 60)
+    }
+}
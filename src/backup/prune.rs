@@ -1,33 +1,262 @@
-use anyhow::{Result, bail};
+use std::cmp::Reverse;
+use std::ops::RangeInclusive;
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::Serialize;
 
-use crate::storage::Database;
+use crate::error::{Error, Result};
+use crate::storage::{Database, SnapshotFilter};
+use crate::storage::database::{SizeChange, SnapshotRecord};
 
 pub struct Prune {
     db: Database,
+    delete_now: bool,
+}
+
+/// Restricts which snapshots a prune may consider. Empty fields do not restrict anything.
+#[derive(Debug, Default)]
+pub struct PruneScope {
+    /// Every one of these tags must be present.
+    pub tags: Vec<String>,
+    /// One of the snapshot's sources must be this directory.
+    pub target_directory: Option<String>,
+}
+
+impl PruneScope {
+    fn matches(&self, snapshot: &SnapshotRecord) -> bool {
+        self.tags.iter().all(|tag| snapshot.tags.contains(tag))
+            && self.target_directory.as_ref()
+                .is_none_or(|dir| SnapshotFilter::Path(dir.clone()).matches(snapshot))
+    }
+}
+
+/// Snapshots chosen by number, range or age for `Prune::prune_matching`.
+#[derive(Debug, Default)]
+pub struct PruneSelection {
+    pub ids: Vec<u32>,
+    pub ranges: Vec<RangeInclusive<u32>>,
+    pub older_than: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PruneReport {
+    pub snapshot_id: u32,
+    /// The snapshot was only forgotten; `gc` deletes it once its grace period has passed.
+    pub forgotten: bool,
+    pub packs_repacked: usize,
+    pub bytes_reclaimed: u64,
+    pub database_size: SizeChange,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RetentionReport {
+    pub kept: Vec<u32>,
+    pub removed: Vec<u32>,
+    /// The removed snapshots were only forgotten; `gc` deletes them once their grace period has passed.
+    pub forgotten: bool,
+    pub packs_repacked: usize,
+    pub bytes_reclaimed: u64,
+    pub database_size: SizeChange,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ForgetReport {
+    pub forgotten: Vec<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GcReport {
+    /// Forgotten snapshots whose grace period had passed and that are now gone for good.
+    pub snapshots_removed: Vec<u32>,
+    pub files_removed: u64,
+    pub blocks_removed: u64,
+    pub bytes_reclaimed: u64,
+    pub packs_repacked: usize,
+    pub database_size: SizeChange,
+}
+
+/// What removing snapshots freed; nothing when they were only forgotten.
+struct Removal {
+    packs_repacked: usize,
+    bytes_reclaimed: u64,
+    database_size: SizeChange,
 }
 
 impl Prune {
     pub fn new(db: Database) -> Self {
-        Prune { db }
+        Prune { db, delete_now: false }
     }
 
-    pub fn prune_snapshot(&self, snapshot_id: u32) -> Result<()> {
-        // Check if snapshot exists
-        if !self.db.snapshot_exists(snapshot_id)? {
-            bail!("Snapshot {} does not exist", snapshot_id);
+    /// Delete pruned snapshots and their unreferenced data right away, without a grace
+    /// period, instead of forgetting them for a later `gc`.
+    pub fn delete_now(mut self, delete_now: bool) -> Self {
+        self.delete_now = delete_now;
+        self
+    }
+
+    /// Forgets a snapshot, or deletes it with its unreferenced data when `delete_now` is set.
+    pub fn prune_snapshot(&self, snapshot_id: u32) -> Result<PruneReport> {
+        // Check if snapshot exists and may be deleted
+        if self.db.snapshot_is_protected(snapshot_id)? {
+            return Err(Error::Protected(snapshot_id));
         }
 
-        println!("Pruning snapshot {}", snapshot_id);
+        let removal = self.remove(&[snapshot_id])?;
 
-        // Delete the snapshot
-        self.db.delete_snapshot(snapshot_id)?;
+        Ok(PruneReport {
+            snapshot_id,
+            forgotten: !self.delete_now,
+            packs_repacked: removal.packs_repacked,
+            bytes_reclaimed: removal.bytes_reclaimed,
+            database_size: removal.database_size,
+        })
+    }
 
-        // Clean up orphaned content
-        self.db.cleanup_orphaned_content()?;
+    /// Keeps the newest `keep_last` snapshots in `scope` and prunes the rest of them.
+    /// Snapshots outside the scope are not touched. Protected snapshots are always kept and
+    /// do not count towards `keep_last`.
+    pub fn keep_last(&self, keep_last: usize, scope: &PruneScope) -> Result<RetentionReport> {
+        let (protected, mut candidates): (Vec<_>, Vec<_>) = self.db.get_snapshots()?
+            .into_iter()
+            .filter(|s| scope.matches(s))
+            .partition(|s| s.protected);
+        candidates.sort_by_key(|s| Reverse((s.timestamp, s.id)));
 
-        println!("Snapshot {} pruned successfully", snapshot_id);
-        println!("Orphaned content cleaned up");
+        let mut kept: Vec<u32> = candidates.iter().take(keep_last).map(|s| s.id).collect();
+        kept.extend(protected.iter().map(|s| s.id));
+        let removed: Vec<u32> = candidates.iter().skip(keep_last).map(|s| s.id).collect();
 
-        Ok(())
+        let mut report = self.prune_snapshots(&removed)?;
+        report.kept = kept;
+        Ok(report)
     }
-}
\ No newline at end of file
+
+    /// Prunes every snapshot in `scope` that is named in `ids`, falls in one of `ranges`
+    /// or is older than `older_than`; all of the given criteria must hold. Snapshots named
+    /// in `ids` must exist and match the rest of the criteria, and protected ones are refused.
+    /// Protected snapshots that only matched a range or age are kept instead.
+    pub fn prune_matching(&self, selection: &PruneSelection, scope: &PruneScope) -> Result<RetentionReport> {
+        let snapshots: Vec<SnapshotRecord> = self.db.get_snapshots()?;
+        let named = !selection.ids.is_empty() || !selection.ranges.is_empty();
+        let selected: Vec<&SnapshotRecord> = snapshots.iter()
+            .filter(|s| scope.matches(s))
+            .filter(|s| !named || selection.ids.contains(&s.id) || selection.ranges.iter().any(|r| r.contains(&s.id)))
+            .filter(|s| selection.older_than.is_none_or(|cutoff| s.timestamp < cutoff))
+            .collect();
+
+        for &snapshot_id in &selection.ids {
+            let snapshot = selected.iter().find(|s| s.id == snapshot_id);
+            match snapshot {
+                None if self.db.snapshot_exists(snapshot_id)? => {
+                    return Err(Error::InvalidInput(format!(
+                        "snapshot {} does not match the other prune criteria", snapshot_id
+                    )));
+                }
+                None => return Err(Error::SnapshotNotFound(snapshot_id)),
+                Some(snapshot) if snapshot.protected => return Err(Error::Protected(snapshot_id)),
+                Some(_) => {}
+            }
+        }
+
+        let (protected, removed): (Vec<&SnapshotRecord>, Vec<_>) = selected.into_iter().partition(|s| s.protected);
+        let removed: Vec<u32> = removed.iter().map(|s| s.id).collect();
+        let mut report = self.prune_snapshots(&removed)?;
+        report.kept = protected.iter().map(|s| s.id).collect();
+        Ok(report)
+    }
+
+    fn prune_snapshots(&self, snapshot_ids: &[u32]) -> Result<RetentionReport> {
+        let removal = self.remove(snapshot_ids)?;
+
+        Ok(RetentionReport {
+            kept: Vec::new(),
+            removed: snapshot_ids.to_vec(),
+            forgotten: !self.delete_now,
+            packs_repacked: removal.packs_repacked,
+            bytes_reclaimed: removal.bytes_reclaimed,
+            database_size: removal.database_size,
+        })
+    }
+
+    /// Forgets the snapshots in one transaction or, with `delete_now`, deletes them in one
+    /// transaction and then cleans up and repacks once.
+    fn remove(&self, snapshot_ids: &[u32]) -> Result<Removal> {
+        let size_before = self.db.file_size()?;
+        if !self.delete_now {
+            self.db.transaction(|| snapshot_ids.iter().try_for_each(|&snapshot_id| self.db.forget_snapshot(snapshot_id)))?;
+            return Ok(Removal {
+                packs_repacked: 0,
+                bytes_reclaimed: 0,
+                database_size: SizeChange { size_before, size_after: size_before },
+            });
+        }
+
+        let cleanup = self.db.transaction(|| {
+            for &snapshot_id in snapshot_ids {
+                self.db.delete_snapshot(snapshot_id)?;
+            }
+            self.db.cleanup_orphaned_content()
+        })?;
+        let packs_repacked = self.db.repack()?;
+
+        Ok(Removal {
+            packs_repacked,
+            bytes_reclaimed: cleanup.bytes_reclaimed,
+            database_size: self.reclaim_space(size_before)?,
+        })
+    }
+
+    /// Hides snapshots without deleting any data. They can be undeleted until `gc` runs
+    /// after their grace period.
+    pub fn forget(&self, snapshot_ids: &[u32]) -> Result<ForgetReport> {
+        self.db.transaction(|| {
+            for &snapshot_id in snapshot_ids {
+                if self.db.snapshot_is_protected(snapshot_id)? {
+                    return Err(Error::Protected(snapshot_id));
+                }
+                self.db.forget_snapshot(snapshot_id)?;
+            }
+            Ok(())
+        })?;
+
+        Ok(ForgetReport { forgotten: snapshot_ids.to_vec() })
+    }
+
+    pub fn undelete(&self, snapshot_id: u32) -> Result<()> {
+        self.db.undelete_snapshot(snapshot_id)
+    }
+
+    /// Removes snapshots forgotten more than `grace` ago, then every file and content block
+    /// no remaining snapshot refers to.
+    pub fn gc(&self, grace: TimeDelta) -> Result<GcReport> {
+        let cutoff = Utc::now() - grace;
+        let snapshots_removed: Vec<u32> = self.db.list_forgotten_snapshots()?
+            .into_iter()
+            .filter(|s| s.deleted_at <= cutoff)
+            .map(|s| s.id)
+            .collect();
+
+        let size_before = self.db.file_size()?;
+        let cleanup = self.db.transaction(|| {
+            for &snapshot_id in &snapshots_removed {
+                self.db.delete_snapshot(snapshot_id)?;
+            }
+            self.db.cleanup_orphaned_content()
+        })?;
+        let packs_repacked = self.db.repack()?;
+
+        Ok(GcReport {
+            snapshots_removed,
+            files_removed: cleanup.files_removed,
+            blocks_removed: cleanup.blocks_removed,
+            bytes_reclaimed: cleanup.bytes_reclaimed,
+            packs_repacked,
+            database_size: self.reclaim_space(size_before)?,
+        })
+    }
+
+    /// Shrinks the database file by the pages the deletions freed.
+    fn reclaim_space(&self, size_before: u64) -> Result<SizeChange> {
+        self.db.reclaim_space()?;
+        Ok(SizeChange { size_before, size_after: self.db.file_size()? })
+    }
+}
@@ -0,0 +1,292 @@
+use std::collections::{HashMap, HashSet};
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::error::{Error, Result};
+use crate::storage::Database;
+use crate::storage::database::SnapshotSource;
+use crate::storage::object_store::{ObjectStore, content_key, pack_key, INDEX_KEY};
+use crate::storage::pack::{PackBuilder, PACK_BLOB_THRESHOLD};
+use crate::utils::hash_content;
+use super::check_foreign_path;
+
+/// Metadata index stored next to the content objects in a remote.
+#[derive(Serialize, Deserialize, Default)]
+struct RemoteIndex {
+    snapshots: Vec<RemoteSnapshot>,
+    #[serde(default)]
+    blobs: HashMap<String, BlobLocation>,
+    /// Chunk hashes of content stored as chunks; the chunks themselves are listed in `blobs`.
+    #[serde(default)]
+    chunks: HashMap<String, Vec<String>>,
+}
+
+/// Where a content block lives: its own object, or a range inside a pack object.
+#[derive(Serialize, Deserialize, Clone)]
+struct BlobLocation {
+    key: String,
+    offset: u64,
+    length: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RemoteSnapshot {
+    /// Missing from indexes pushed before snapshots had UUIDs.
+    #[serde(default)]
+    uuid: Option<String>,
+    timestamp: DateTime<Utc>,
+    target_directory: String,
+    #[serde(default)]
+    message: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    protected: bool,
+    #[serde(default)]
+    sources: Vec<SnapshotSource>,
+    files: Vec<RemoteFile>,
+}
+
+impl RemoteSnapshot {
+    /// Same rule as pull: a matching UUID, or the same time and directory for entries
+    /// pushed before snapshots had UUIDs.
+    fn is_same(&self, other: &RemoteSnapshot) -> bool {
+        let same_uuid = matches!((&self.uuid, &other.uuid), (Some(a), Some(b)) if a == b);
+        same_uuid || (self.timestamp == other.timestamp && self.target_directory == other.target_directory)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct RemoteFile {
+    path: String,
+    content_hash: String,
+    size: u64,
+    #[serde(default)]
+    mode: Option<u32>,
+    #[serde(default)]
+    mtime: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PushReport {
+    pub snapshots_in_remote: usize,
+    pub blocks_uploaded: u64,
+    pub packs_uploaded: u64,
+    pub bytes_uploaded: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PullReport {
+    pub snapshots_pulled: u64,
+    pub blocks_downloaded: u64,
+    pub bytes_downloaded: u64,
+}
+
+pub struct Remote {
+    db: Database,
+    store: Box<dyn ObjectStore>,
+}
+
+impl Remote {
+    pub fn new(db: Database, store: Box<dyn ObjectStore>) -> Self {
+        Remote { db, store }
+    }
+
+    pub fn push(&self) -> Result<PushReport> {
+        let mut index = self.fetch_index()?;
+
+        let mut uploaded_blocks = 0;
+        let mut uploaded_bytes = 0;
+        let mut uploaded_packs = 0;
+        let mut pack = PackBuilder::default();
+
+        for hash in self.db.get_content_hashes()? {
+            if index.blobs.contains_key(&hash) || index.chunks.contains_key(&hash) {
+                continue;
+            }
+
+            let chunks = self.db.get_content_chunks(&hash)?;
+            if !chunks.is_empty() {
+                index.chunks.insert(hash, chunks);
+                continue;
+            }
+
+            let content = self.db.get_file_content(&hash)?;
+            uploaded_blocks += 1;
+            uploaded_bytes += content.len() as u64;
+
+            if content.len() < PACK_BLOB_THRESHOLD {
+                pack.add(&hash, &content);
+                if pack.is_full() {
+                    self.upload_pack(&mut pack, &mut index)?;
+                    uploaded_packs += 1;
+                }
+            } else {
+                let key = content_key(&hash);
+                self.store.put(&key, &content)?;
+                index.blobs.insert(hash, BlobLocation { key, offset: 0, length: content.len() as u64 });
+            }
+        }
+
+        if !pack.is_empty() {
+            self.upload_pack(&mut pack, &mut index)?;
+            uploaded_packs += 1;
+        }
+
+        // The index goes last so it never references content that is not uploaded yet.
+        // Entries only the remote has (forgotten here, or pushed by another host) are kept.
+        for snapshot in self.db.get_snapshots()? {
+            let files = self.db.get_snapshot_files(snapshot.id)?
+                .into_iter()
+                .map(|f| RemoteFile { path: f.path, content_hash: f.content_hash, size: f.size, mode: f.mode, mtime: f.mtime })
+                .collect();
+            let remote = RemoteSnapshot {
+                uuid: Some(snapshot.uuid),
+                timestamp: snapshot.timestamp,
+                target_directory: snapshot.target_directory,
+                message: snapshot.message,
+                tags: snapshot.tags,
+                protected: snapshot.protected,
+                sources: snapshot.sources,
+                files,
+            };
+            match index.snapshots.iter().position(|existing| existing.is_same(&remote)) {
+                Some(position) => index.snapshots[position] = remote,
+                None => index.snapshots.push(remote),
+            }
+        }
+        self.store.put(INDEX_KEY, &serde_json::to_vec(&index)?)?;
+
+        Ok(PushReport {
+            snapshots_in_remote: index.snapshots.len(),
+            blocks_uploaded: uploaded_blocks,
+            packs_uploaded: uploaded_packs,
+            bytes_uploaded: uploaded_bytes,
+        })
+    }
+
+    fn upload_pack(&self, pack: &mut PackBuilder, index: &mut RemoteIndex) -> Result<()> {
+        let (data, entries) = pack.take();
+        let key = pack_key(&hash_content(&data));
+        self.store.put(&key, &data)?;
+
+        for entry in entries {
+            index.blobs.insert(entry.hash, BlobLocation {
+                key: key.clone(),
+                offset: entry.offset,
+                length: entry.length,
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn pull(&self) -> Result<PullReport> {
+        self.db.transaction(|| self.pull_snapshots())
+    }
+
+    fn pull_snapshots(&self) -> Result<PullReport> {
+        let remote_index = self.fetch_index()?;
+        let local: HashSet<(DateTime<Utc>, String)> = self.db.get_snapshots()?
+            .into_iter()
+            .map(|s| (s.timestamp, s.target_directory))
+            .collect();
+
+        let mut pulled_snapshots = 0;
+        let mut downloaded_blocks = 0;
+        let mut downloaded_bytes = 0;
+        // Consecutive blobs usually come from the same pack, so keep the last object around
+        let mut cached: Option<(String, Vec<u8>)> = None;
+
+        for snapshot in &remote_index.snapshots {
+            // Snapshots pulled before UUIDs existed are only recognisable by time and directory
+            if local.contains(&(snapshot.timestamp, snapshot.target_directory.clone())) {
+                continue;
+            }
+            for file in &snapshot.files {
+                check_foreign_path(&file.path)?;
+            }
+            let snapshot_id = match &snapshot.uuid {
+                Some(uuid) if self.db.find_snapshot_by_uuid(uuid)?.is_some() => continue,
+                Some(uuid) => self.db.insert_snapshot_with_uuid(&snapshot.timestamp, &snapshot.target_directory, uuid)?,
+                None => self.db.insert_snapshot(&snapshot.timestamp, &snapshot.target_directory)?,
+            };
+            self.db.set_snapshot_message(snapshot_id, snapshot.message.as_deref())?;
+            self.db.set_snapshot_protected(snapshot_id, snapshot.protected)?;
+            for source in &snapshot.sources {
+                self.db.add_snapshot_source(snapshot_id, source)?;
+            }
+            for tag in &snapshot.tags {
+                self.db.add_snapshot_tag(snapshot_id, tag)?;
+            }
+            for file in &snapshot.files {
+                if !self.db.content_exists(&file.content_hash)? {
+                    let chunks = remote_index.chunks.get(&file.content_hash);
+                    let blocks = chunks.map(Vec::as_slice).unwrap_or(std::slice::from_ref(&file.content_hash));
+                    for hash in blocks {
+                        if !self.db.content_exists(hash)? {
+                            downloaded_bytes += self.download_block(&remote_index, hash, &mut cached)?;
+                            downloaded_blocks += 1;
+                        }
+                    }
+                    if let Some(chunks) = chunks {
+                        self.db.store_chunked_content(&file.content_hash, file.size, chunks)?;
+                    }
+                }
+                self.db.add_file_with_metadata(snapshot_id, &file.path, &file.content_hash, file.size, file.mode, file.mtime)?;
+            }
+            pulled_snapshots += 1;
+        }
+
+        Ok(PullReport {
+            snapshots_pulled: pulled_snapshots,
+            blocks_downloaded: downloaded_blocks,
+            bytes_downloaded: downloaded_bytes,
+        })
+    }
+
+    /// Downloads one block into the database and returns its size.
+    fn download_block(&self, index: &RemoteIndex, hash: &str, cached: &mut Option<(String, Vec<u8>)>) -> Result<u64> {
+        let location = index.blobs.get(hash)
+            .ok_or_else(|| Error::Corruption(format!("remote index has no location for content block {}", hash)))?;
+        if cached.as_ref().map(|(key, _)| key != &location.key).unwrap_or(true) {
+            let data = self.store.get(&location.key)?;
+            *cached = Some((location.key.clone(), data));
+        }
+
+        let data = &cached.as_ref().unwrap().1;
+        let start = location.offset as usize;
+        let content = data.get(start..start + location.length as usize)
+            .ok_or_else(|| Error::Corruption(format!("remote object {} is truncated", location.key)))?;
+        if hash_content(content) != hash {
+            return Err(Error::Corruption(format!("remote content block {} does not match its hash", hash)));
+        }
+        self.db.store_content(hash, content)?;
+
+        Ok(content.len() as u64)
+    }
+
+    fn fetch_index(&self) -> Result<RemoteIndex> {
+        if !self.store.exists(INDEX_KEY)? {
+            return Ok(RemoteIndex::default());
+        }
+
+        let data = self.store.get(INDEX_KEY)?;
+        let mut index: RemoteIndex = serde_json::from_slice(&data)
+            .map_err(|e| Error::Corruption(format!("remote snapshot index is unreadable: {}", e)))?;
+
+        // Older indexes only listed files; their blocks were stored as individual objects
+        for file in index.snapshots.iter().flat_map(|s| s.files.iter()) {
+            if index.chunks.contains_key(&file.content_hash) {
+                continue;
+            }
+            index.blobs.entry(file.content_hash.clone()).or_insert_with(|| BlobLocation {
+                key: content_key(&file.content_hash),
+                offset: 0,
+                length: file.size,
+            });
+        }
+
+        Ok(index)
+    }
+}
@@ -1,75 +1,213 @@
-use std::path::Path;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 use std::fs;
-use anyhow::{Result, Context, bail};
+use std::io::Write;
+use std::time::{Duration, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
 
+use crate::error::{Error, Result};
 use crate::storage::Database;
+use crate::storage::database::FileInfo;
+use super::FileError;
+use super::progress::{Progress, NoProgress};
+
+/// Files are written in chunks of this size so progress can be reported within large files.
+const WRITE_CHUNK_SIZE: usize = 1024 * 1024;
 
 pub struct Restore {
     db: Database,
+    progress: Box<dyn Progress>,
+    continue_on_error: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RestoreReport {
+    pub snapshot_id: u32,
+    pub output_directory: PathBuf,
+    pub files_restored: u64,
+    pub total_size: u64,
+    pub failed_files: Vec<FileError>,
 }
 
 impl Restore {
     pub fn new(db: Database) -> Self {
-        Restore { db }
+        Restore { db, progress: Box::new(NoProgress), continue_on_error: false }
+    }
+
+    /// Keep restoring after a file fails instead of returning the first error.
+    /// Failures are then listed in the report's `failed_files`.
+    pub fn continue_on_error(mut self, continue_on_error: bool) -> Self {
+        self.continue_on_error = continue_on_error;
+        self
+    }
+
+    pub fn with_progress(mut self, progress: Box<dyn Progress>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    pub fn restore_snapshot(&self, snapshot_id: u32, output_directory: &Path) -> Result<RestoreReport> {
+        stopped_error(self.restore_with_report(snapshot_id, output_directory, None)?)
     }
 
-    pub fn restore_snapshot(&self, snapshot_id: u32, output_directory: &Path) -> Result<()> {
+    /// Restores only the given snapshot paths, e.g. the failures of an earlier restore.
+    pub fn restore_paths(&self, snapshot_id: u32, output_directory: &Path, paths: &[String]) -> Result<RestoreReport> {
+        stopped_error(self.restore_with_report(snapshot_id, output_directory, Some(paths))?)
+    }
+
+    /// Restores the whole snapshot, or only `paths`, and also returns the report when the
+    /// restore stops at a failure. The failed file and the files not attempted are then
+    /// listed in `failed_files`, so the report can be used to retry them.
+    pub fn restore_with_report(
+        &self,
+        snapshot_id: u32,
+        output_directory: &Path,
+        paths: Option<&[String]>,
+    ) -> Result<(RestoreReport, Option<Error>)> {
+        let paths: Option<HashSet<&str>> = paths.map(|paths| paths.iter().map(String::as_str).collect());
+        self.restore_files(snapshot_id, output_directory, paths.as_ref())
+    }
+
+    /// Writes the content of one file of a snapshot to `writer`, returning its size.
+    pub fn cat(&self, snapshot_id: u32, path: &str, writer: &mut dyn Write) -> Result<u64> {
+        if !self.db.snapshot_exists(snapshot_id)? {
+            return Err(Error::SnapshotNotFound(snapshot_id));
+        }
+
+        let file = self.db.get_snapshot_files(snapshot_id)?
+            .into_iter()
+            .find(|f| f.path == path)
+            .ok_or_else(|| Error::InvalidInput(format!("{} is not part of snapshot {}", path, snapshot_id)))?;
+
+        let mut size = 0;
+        self.db.read_file_content(&file.content_hash, |content| {
+            writer.write_all(content).map_err(|e| Error::io(Path::new("<stdout>"), e))?;
+            size += content.len() as u64;
+            Ok(())
+        })?;
+        writer.flush().map_err(|e| Error::io(Path::new("<stdout>"), e))?;
+
+        Ok(size)
+    }
+
+    fn restore_files(
+        &self,
+        snapshot_id: u32,
+        output_directory: &Path,
+        only: Option<&HashSet<&str>>,
+    ) -> Result<(RestoreReport, Option<Error>)> {
         // Check if snapshot exists
         if !self.db.snapshot_exists(snapshot_id)? {
-            bail!("Snapshot {} does not exist", snapshot_id);
+            return Err(Error::SnapshotNotFound(snapshot_id));
         }
 
         // Create output directory if it doesn't exist
         if !output_directory.exists() {
             fs::create_dir_all(output_directory)
-                .with_context(|| format!("Failed to create output directory: {}", output_directory.display()))?;
+                .map_err(|e| Error::io(output_directory, e))?;
         }
 
         // Get all files in the snapshot
-        let files = self.db.get_snapshot_files(snapshot_id)?;
-        
-        println!("Restoring snapshot {} to {}", snapshot_id, output_directory.display());
-        println!("Files to restore: {}", files.len());
+        let mut files = self.db.get_snapshot_files(snapshot_id)?;
+        if let Some(only) = only {
+            files.retain(|f| only.contains(f.path.as_str()));
+            if files.len() < only.len() {
+                return Err(Error::InvalidInput(format!(
+                    "{} requested path(s) are not part of snapshot {}",
+                    only.len() - files.len(), snapshot_id
+                )));
+            }
+        }
+        self.progress.start(files.len() as u64, files.iter().map(|f| f.size).sum());
 
         let mut restored_count = 0;
         let mut total_size = 0;
+        let mut failed_files = Vec::new();
+        let mut stopped = None;
 
-        for file_info in files {
-            match self.restore_file(&file_info.path, &file_info.content_hash, output_directory) {
+        let mut files = files.into_iter();
+        for file_info in files.by_ref() {
+            self.progress.file_started(&file_info.path);
+            let result = self.restore_file(&file_info, output_directory);
+            self.progress.file_finished(&file_info.path);
+
+            match result {
                 Ok(size) => {
                     restored_count += 1;
                     total_size += size;
                 }
                 Err(e) => {
-                    eprintln!("Warning: Failed to restore file {}: {}", file_info.path, e);
+                    failed_files.push(FileError::new(file_info.path, &e));
+                    if !self.continue_on_error {
+                        stopped = Some(e);
+                        break;
+                    }
                 }
             }
         }
+        for file_info in files {
+            failed_files.push(FileError {
+                path: file_info.path,
+                error: "not attempted, the restore stopped at an earlier failure".to_string(),
+            });
+        }
 
-        println!("Restore completed successfully");
-        println!("  Files restored: {}", restored_count);
-        println!("  Total size: {} bytes", total_size);
+        self.progress.finish();
 
-        Ok(())
+        let report = RestoreReport {
+            snapshot_id,
+            output_directory: output_directory.to_path_buf(),
+            files_restored: restored_count,
+            total_size,
+            failed_files,
+        };
+        Ok((report, stopped))
     }
 
-    fn restore_file(&self, relative_path: &str, content_hash: &str, output_directory: &Path) -> Result<u64> {
-        let file_path = output_directory.join(relative_path);
+    fn restore_file(&self, file_info: &FileInfo, output_directory: &Path) -> Result<u64> {
+        let file_path = output_directory.join(&file_info.path);
         
         // Create parent directories if they don't exist
         if let Some(parent) = file_path.parent() {
             fs::create_dir_all(parent)
-                .with_context(|| format!("Failed to create parent directory: {}", parent.display()))?;
+                .map_err(|e| Error::io(parent, e))?;
         }
 
-        // Get file content from database
-        let content = self.db.get_file_content(content_hash)
-            .with_context(|| format!("Failed to get content for hash: {}", content_hash))?;
+        // Stream file content from the database into the file
+        let mut file = fs::File::create(&file_path)
+            .map_err(|e| Error::io(&file_path, e))?;
+        let mut size = 0;
+        self.db.read_file_content(&file_info.content_hash, |content| {
+            for chunk in content.chunks(WRITE_CHUNK_SIZE) {
+                file.write_all(chunk)
+                    .map_err(|e| Error::io(&file_path, e))?;
+                self.progress.bytes_processed(chunk.len() as u64);
+            }
+            size += content.len() as u64;
+            Ok(())
+        })?;
+
+        // Permissions and timestamps are only known for imported archives
+        if let Some(mtime) = file_info.mtime {
+            let modified = UNIX_EPOCH + Duration::from_secs(mtime.max(0) as u64);
+            file.set_modified(modified)
+                .map_err(|e| Error::io(&file_path, e))?;
+        }
+        #[cfg(unix)]
+        if let Some(mode) = file_info.mode {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&file_path, fs::Permissions::from_mode(mode))
+                .map_err(|e| Error::io(&file_path, e))?;
+        }
 
-        // Write content to file
-        fs::write(&file_path, &content)
-            .with_context(|| format!("Failed to write file: {}", file_path.display()))?;
+        Ok(size)
+    }
+}
 
-        Ok(content.len() as u64)
+/// The report of a restore that finished, or the error it stopped at.
+fn stopped_error((report, stopped): (RestoreReport, Option<Error>)) -> Result<RestoreReport> {
+    match stopped {
+        Some(e) => Err(e),
+        None => Ok(report),
     }
 }
\ No newline at end of file
@@ -1,67 +1,358 @@
-use std::path::Path;
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
 use std::fs;
+use std::io::{self, BufRead, Read};
 use walkdir::WalkDir;
-use anyhow::{Result, Context};
+use serde::Serialize;
 
+use crate::error::{Error, Result};
 use crate::storage::Database;
-use crate::utils::{hash_content, relative_path};
+use crate::storage::chunker::Chunker;
+use crate::storage::database::SnapshotSource;
+use super::FileError;
+use super::progress::{Progress, NoProgress};
+use crate::utils::{hash_content, relative_path, ContentHasher, Excludes};
+
+/// Files are read in chunks of this size so progress can be reported within large files.
+const READ_CHUNK_SIZE: usize = 1024 * 1024;
 
 pub struct Snapshot {
     db: Database,
+    progress: Box<dyn Progress>,
+    strict: bool,
+    tags: Vec<String>,
+    message: Option<String>,
+    excludes: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SnapshotSummary {
+    pub snapshot_id: u32,
+    pub target_directory: String,
+    pub sources: Vec<SnapshotSource>,
+    pub files_processed: u64,
+    pub total_size: u64,
+    /// Bytes of content that were not already in the repository.
+    pub bytes_added: u64,
+    pub deduplicated_files: u64,
+    pub skipped_files: Vec<FileError>,
+    pub tags: Vec<String>,
+    pub message: Option<String>,
 }
 
 impl Snapshot {
     pub fn new(db: Database) -> Self {
-        Snapshot { db }
+        Snapshot {
+            db,
+            progress: Box::new(NoProgress),
+            strict: false,
+            tags: Vec::new(),
+            message: None,
+            excludes: Vec::new(),
+        }
+    }
+
+    pub fn with_tags(mut self, tags: Vec<String>) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    /// Free-form description stored with the snapshot.
+    pub fn with_message(mut self, message: Option<String>) -> Self {
+        self.message = message;
+        self
+    }
+
+    /// Glob patterns of files and directories to leave out of directory snapshots.
+    pub fn with_excludes(mut self, excludes: Vec<String>) -> Self {
+        self.excludes = excludes;
+        self
+    }
+
+    /// In strict mode the first unreadable path aborts the snapshot and nothing is stored.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    pub fn with_progress(mut self, progress: Box<dyn Progress>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    pub fn create(&self, target_directory: &Path) -> Result<SnapshotSummary> {
+        self.create_from_sources(&[target_directory.to_path_buf()])
+    }
+
+    /// Backs up several directories into one snapshot. File paths are prefixed with the
+    /// name of their source directory so they stay distinct between sources.
+    pub fn create_from_sources(&self, target_directories: &[PathBuf]) -> Result<SnapshotSummary> {
+        let sources = source_names(target_directories)?;
+        self.db.transaction(|| self.create_snapshot(target_directories, &sources))
+    }
+
+    /// Stores everything read from `reader` as a single file called `filename`. The stream is
+    /// split into content-defined chunks, so data repeated between runs is only stored once.
+    pub fn create_from_reader(&self, reader: impl Read, filename: &str) -> Result<SnapshotSummary> {
+        let path = Path::new(filename);
+        if filename.is_empty() || !path.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(Error::InvalidInput(format!("{:?} is not a valid relative file name", filename)));
+        }
+
+        let source = SnapshotSource { name: "stdin".to_string(), path: "-".to_string() };
+        self.db.transaction(|| {
+            let snapshot_id = self.begin_snapshot(std::slice::from_ref(&source))?;
+
+            self.progress.start(1, 0);
+            self.progress.file_started(filename);
+            let stored = self.store_stream(reader, Path::new("<stdin>"))?;
+            self.db.add_file_to_snapshot(snapshot_id, filename, &stored.content_hash, stored.size)?;
+            self.progress.file_finished(filename);
+            self.progress.finish();
+
+            Ok(SnapshotSummary {
+                snapshot_id,
+                target_directory: source.path.clone(),
+                sources: vec![source.clone()],
+                files_processed: 1,
+                total_size: stored.size,
+                bytes_added: stored.bytes_added,
+                deduplicated_files: stored.was_deduplicated as u64,
+                skipped_files: Vec::new(),
+                tags: self.db.get_snapshot_tags(snapshot_id)?,
+                message: self.message.clone(),
+            })
+        })
     }
 
-    pub fn create(&self, target_directory: &Path) -> Result<u32> {
-        let target_dir_str = target_directory.to_string_lossy().to_string();
-        let snapshot_id = self.db.create_snapshot(&target_dir_str)?;
+    /// Imports the regular files of a tar archive, optionally gzip or zstd compressed, as a
+    /// snapshot. Entries are read straight from the archive and keep their modes and mtimes.
+    pub fn create_from_archive(&self, archive: &Path) -> Result<SnapshotSummary> {
+        let reader = open_archive(archive)?;
+        let source = SnapshotSource::from_path(&archive.to_string_lossy());
+        self.db.transaction(|| {
+            let snapshot_id = self.begin_snapshot(std::slice::from_ref(&source))?;
+            let io_error = |e| Error::io(archive, e);
+
+            let mut file_count = 0;
+            let mut total_size = 0;
+            let mut bytes_added = 0;
+            let mut deduplicated_files = 0;
+            let mut skipped_files = Vec::new();
+
+            // The number of entries is unknown until the whole archive has been read
+            self.progress.start(0, 0);
+            let mut entries = tar::Archive::new(reader);
+            for entry in entries.entries().map_err(io_error)? {
+                // The archive cannot be read past a broken header, so this also ends the loop
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) => {
+                        self.skip(snapshot_id, &mut skipped_files, &source.path, io_error(e))?;
+                        continue;
+                    }
+                };
+                if !entry.header().entry_type().is_file() {
+                    continue;
+                }
+
+                let raw_path = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+                let path = match archive_entry_path(Path::new(&raw_path)) {
+                    Ok(path) => path,
+                    Err(e) => {
+                        self.skip(snapshot_id, &mut skipped_files, &raw_path, e)?;
+                        continue;
+                    }
+                };
+                let mode = entry.header().mode().ok().map(|mode| mode & 0o7777);
+                let mtime = entry.header().mtime().ok().map(|mtime| mtime as i64);
+
+                // A truncated archive ends the entry early instead of failing the read
+                let expected_size = entry.size();
+                self.progress.file_started(&path);
+                let stored = self.store_stream(entry, archive).and_then(|stored| {
+                    if stored.size == expected_size {
+                        Ok(stored)
+                    } else {
+                        Err(io_error(io::Error::from(io::ErrorKind::UnexpectedEof)))
+                    }
+                });
+                self.progress.file_finished(&path);
+                let stored = match stored {
+                    Ok(stored) => stored,
+                    Err(e) => {
+                        self.skip(snapshot_id, &mut skipped_files, &path, e)?;
+                        continue;
+                    }
+                };
+                self.db.add_file_with_metadata(snapshot_id, &path, &stored.content_hash, stored.size, mode, mtime)?;
+
+                file_count += 1;
+                total_size += stored.size;
+                bytes_added += stored.bytes_added;
+                deduplicated_files += stored.was_deduplicated as u64;
+            }
+            self.progress.finish();
+
+            Ok(SnapshotSummary {
+                snapshot_id,
+                target_directory: source.path.clone(),
+                sources: vec![source.clone()],
+                files_processed: file_count,
+                total_size,
+                bytes_added,
+                deduplicated_files,
+                skipped_files,
+                tags: self.db.get_snapshot_tags(snapshot_id)?,
+                message: self.message.clone(),
+            })
+        })
+    }
+
+    /// Stores a stream as content-defined chunks and returns the hash of the whole content.
+    fn store_stream(&self, reader: impl Read, source: &Path) -> Result<StoredStream> {
+        let mut hasher = ContentHasher::default();
+        let mut chunk_hashes = Vec::new();
+        let mut size = 0;
+        let mut bytes_added = 0;
+        for chunk in Chunker::new(reader) {
+            let chunk = chunk.map_err(|e| Error::io(source, e))?;
+            let chunk_hash = hash_content(&chunk);
+            if !self.db.store_content(&chunk_hash, &chunk)? {
+                bytes_added += chunk.len() as u64;
+            }
+            hasher.update(&chunk);
+            chunk_hashes.push(chunk_hash);
+            size += chunk.len() as u64;
+            self.progress.bytes_processed(chunk.len() as u64);
+        }
+
+        let content_hash = hasher.finish();
+        // A single chunk is the whole content, so only longer streams need a chunk list
+        let was_deduplicated = match chunk_hashes.len() {
+            0 => self.db.store_content(&content_hash, &[])?,
+            1 => bytes_added == 0,
+            _ => self.db.store_chunked_content(&content_hash, size, &chunk_hashes)?,
+        };
+
+        Ok(StoredStream { content_hash, size, bytes_added, was_deduplicated })
+    }
+
+    /// Creates the snapshot row with its message, tags and sources.
+    fn begin_snapshot(&self, sources: &[SnapshotSource]) -> Result<u32> {
+        let snapshot_id = self.db.create_snapshot(&target_directory_label(sources))?;
+        self.db.set_snapshot_message(snapshot_id, self.message.as_deref())?;
+        for tag in &self.tags {
+            self.db.add_snapshot_tag(snapshot_id, tag)?;
+        }
+        for source in sources {
+            self.db.add_snapshot_source(snapshot_id, source)?;
+        }
+
+        Ok(snapshot_id)
+    }
 
-        println!("Creating snapshot {} for directory: {}", snapshot_id, target_dir_str);
+    fn create_snapshot(&self, target_directories: &[PathBuf], sources: &[SnapshotSource]) -> Result<SnapshotSummary> {
+        let excludes = Excludes::new(&self.excludes)?;
+        let snapshot_id = self.begin_snapshot(sources)?;
 
         let mut file_count = 0;
         let mut total_size = 0;
+        let mut bytes_added = 0;
         let mut deduplicated_files = 0;
+        let mut skipped_files = Vec::new();
 
-        for entry in WalkDir::new(target_directory)
-            .follow_links(false)
-            .into_iter()
-            .filter_map(|e| e.ok())
-        {
-            if entry.file_type().is_file() {
-                let file_path = entry.path();
-                let relative_path = relative_path(file_path, target_directory)?;
-                let relative_path_str = relative_path.to_string_lossy().to_string();
-
-                match self.process_file(snapshot_id, file_path, &relative_path_str) {
-                    Ok(ProcessResult { size, was_deduplicated }) => {
-                        file_count += 1;
-                        total_size += size;
-                        if was_deduplicated {
-                            deduplicated_files += 1;
-                        }
-                    }
+        // Walk first so progress observers know the totals up front
+        let mut entries = Vec::new();
+        for (target_directory, source) in target_directories.iter().zip(sources) {
+            let walk = WalkDir::new(target_directory).follow_links(false).into_iter()
+                .filter_entry(|entry| {
+                    entry.depth() == 0
+                        || !entry.path().strip_prefix(target_directory).is_ok_and(|p| excludes.is_excluded(p))
+                });
+            for entry in walk {
+                match entry {
+                    Ok(entry) if entry.file_type().is_file() => entries.push((target_directory, source, entry)),
+                    Ok(_) => {}
                     Err(e) => {
-                        eprintln!("Warning: Failed to process file {}: {}", file_path.display(), e);
+                        let path = e.path().unwrap_or(target_directory).to_path_buf();
+                        let error = Error::io(&path, io::Error::from(e));
+                        let path = snapshot_path(&path, target_directory, source, sources.len() > 1)?;
+                        self.skip(snapshot_id, &mut skipped_files, &path, error)?;
                     }
                 }
             }
         }
+        let expected_bytes = entries.iter()
+            .filter_map(|(_, _, e)| e.metadata().ok())
+            .map(|m| m.len())
+            .sum();
+        self.progress.start(entries.len() as u64, expected_bytes);
 
-        println!("Snapshot {} created successfully", snapshot_id);
-        println!("  Files processed: {}", file_count);
-        println!("  Total size: {} bytes", total_size);
-        println!("  Deduplicated files: {}", deduplicated_files);
+        for (target_directory, source, entry) in entries {
+            let file_path = entry.path();
+            let relative_path_str = snapshot_path(file_path, target_directory, source, sources.len() > 1)?;
 
-        Ok(snapshot_id)
+            self.progress.file_started(&relative_path_str);
+            match self.process_file(snapshot_id, file_path, &relative_path_str) {
+                Ok(ProcessResult { size, was_deduplicated }) => {
+                    file_count += 1;
+                    total_size += size;
+                    if was_deduplicated {
+                        deduplicated_files += 1;
+                    } else {
+                        bytes_added += size;
+                    }
+                }
+                Err(e) => self.skip(snapshot_id, &mut skipped_files, &relative_path_str, e)?,
+            }
+            self.progress.file_finished(&relative_path_str);
+        }
+
+        self.progress.finish();
+
+        Ok(SnapshotSummary {
+            snapshot_id,
+            target_directory: target_directory_label(sources),
+            sources: sources.to_vec(),
+            files_processed: file_count,
+            total_size,
+            bytes_added,
+            deduplicated_files,
+            skipped_files,
+            tags: self.db.get_snapshot_tags(snapshot_id)?,
+            message: self.message.clone(),
+        })
+    }
+
+    /// Records a path that could not be backed up, or aborts the snapshot in strict mode.
+    /// `path` is relative to the snapshot, like `files.path`.
+    fn skip(&self, snapshot_id: u32, skipped_files: &mut Vec<FileError>, path: &str, error: Error) -> Result<()> {
+        if self.strict {
+            return Err(error);
+        }
+
+        let skipped = FileError::new(path.to_string(), &error);
+        self.db.record_snapshot_error(snapshot_id, &skipped.path, &skipped.error)?;
+        skipped_files.push(skipped);
+        Ok(())
     }
 
     fn process_file(&self, snapshot_id: u32, file_path: &Path, relative_path: &str) -> Result<ProcessResult> {
-        let content = fs::read(file_path)
-            .with_context(|| format!("Failed to read file: {}", file_path.display()))?;
-        
+        let mut file = fs::File::open(file_path)
+            .map_err(|e| Error::io(file_path, e))?;
+        let mut content = Vec::new();
+        let mut buffer = vec![0u8; READ_CHUNK_SIZE];
+        loop {
+            let read = file.read(&mut buffer)
+                .map_err(|e| Error::io(file_path, e))?;
+            if read == 0 {
+                break;
+            }
+            content.extend_from_slice(&buffer[..read]);
+            self.progress.bytes_processed(read as u64);
+        }
+
         let size = content.len() as u64;
         let content_hash = hash_content(&content);
 
@@ -75,6 +366,90 @@ impl Snapshot {
     }
 }
 
+/// Value of `snapshots.target_directory`: the source paths, comma separated.
+fn target_directory_label(sources: &[SnapshotSource]) -> String {
+    sources.iter().map(|s| s.path.as_str()).collect::<Vec<_>>().join(", ")
+}
+
+/// Path of `path` inside the snapshot: relative to its source directory, and under the
+/// source's name when the snapshot has several sources.
+fn snapshot_path(path: &Path, target_directory: &Path, source: &SnapshotSource, several: bool) -> Result<String> {
+    let mut relative = relative_path(path, target_directory)?;
+    if several {
+        relative = Path::new(&source.name).join(relative);
+    }
+    Ok(relative.to_string_lossy().to_string())
+}
+
+/// Names each source after its last path component, adding a numeric suffix to repeated names.
+fn source_names(target_directories: &[PathBuf]) -> Result<Vec<SnapshotSource>> {
+    if target_directories.is_empty() {
+        return Err(Error::InvalidInput("no directories to snapshot".to_string()));
+    }
+
+    let mut names = HashSet::new();
+    let mut paths = HashSet::new();
+    let mut sources = Vec::new();
+    for target_directory in target_directories {
+        let path = target_directory.to_string_lossy().to_string();
+        if !paths.insert(path.clone()) {
+            return Err(Error::InvalidInput(format!("{} is listed more than once", path)));
+        }
+
+        let mut source = SnapshotSource::from_path(&path);
+        let base = source.name.clone();
+        let mut suffix = 2;
+        while !names.insert(source.name.clone()) {
+            source.name = format!("{}-{}", base, suffix);
+            suffix += 1;
+        }
+        sources.push(source);
+    }
+
+    Ok(sources)
+}
+
+/// Opens a tar archive, decompressing it if it starts with a gzip or zstd header.
+fn open_archive(archive: &Path) -> Result<Box<dyn Read>> {
+    let io_error = |e| Error::io(archive, e);
+    let mut reader = io::BufReader::new(fs::File::open(archive).map_err(io_error)?);
+    let magic = reader.fill_buf().map_err(io_error)?;
+
+    if magic.starts_with(&[0x1f, 0x8b]) {
+        Ok(Box::new(flate2::read::MultiGzDecoder::new(reader)))
+    } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        Ok(Box::new(zstd::Decoder::with_buffer(reader).map_err(io_error)?))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// Turns an archive member name into a relative snapshot path, dropping leading `/` and `./`.
+fn archive_entry_path(path: &Path) -> Result<String> {
+    let mut relative = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => relative.push(part),
+            Component::CurDir | Component::RootDir => {}
+            Component::ParentDir | Component::Prefix(_) => {
+                return Err(Error::InvalidInput(format!("archive entry {} escapes the snapshot root", path.display())));
+            }
+        }
+    }
+    if relative.as_os_str().is_empty() {
+        return Err(Error::InvalidInput("archive entry has an empty name".to_string()));
+    }
+
+    Ok(relative.to_string_lossy().to_string())
+}
+
+struct StoredStream {
+    content_hash: String,
+    size: u64,
+    bytes_added: u64,
+    was_deduplicated: bool,
+}
+
 struct ProcessResult {
     size: u64,
     was_deduplicated: bool,
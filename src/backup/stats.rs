@@ -0,0 +1,136 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use serde::Serialize;
+
+use crate::error::Result;
+use crate::storage::Database;
+
+pub struct Stats {
+    db: Database,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StatsReport {
+    pub snapshots: u64,
+    pub forgotten_snapshots: u64,
+    /// File entries across all snapshots; a file kept in ten snapshots counts ten times.
+    pub files: u64,
+    /// Distinct contents those entries refer to.
+    pub distinct_contents: u64,
+    /// Sum of the sizes of all file entries, as if every snapshot were stored in full.
+    pub logical_bytes: u64,
+    /// Bytes of distinct content those snapshots refer to, after deduplication of whole
+    /// files and chunks.
+    pub unique_bytes: u64,
+    /// Bytes the repository holds for all content, including content of forgotten snapshots
+    /// and unrepacked pack space.
+    pub stored_bytes: u64,
+    /// `logical_bytes / unique_bytes`, or 1 for an empty repository.
+    pub dedup_ratio: f64,
+    pub database_size: u64,
+    pub snapshot_stats: Vec<SnapshotStats>,
+    pub top_directories: Vec<DirectoryStats>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SnapshotStats {
+    pub snapshot_id: u32,
+    /// The previous snapshot of the same directories, if any.
+    pub parent: Option<u32>,
+    pub files: u64,
+    pub total_size: u64,
+    /// Size of the distinct contents that its parent did not have.
+    pub added_bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DirectoryStats {
+    pub path: String,
+    /// Size of the contents that no file outside this directory refers to.
+    pub unique_bytes: u64,
+}
+
+impl Stats {
+    pub fn new(db: Database) -> Self {
+        Stats { db }
+    }
+
+    /// Computes repository statistics, listing at most `top_directories` directories.
+    pub fn report(&self, top_directories: usize) -> Result<StatsReport> {
+        let totals = self.db.storage_totals()?;
+        let unique_bytes = self.db.live_unique_bytes()?;
+        let mut snapshots = self.db.get_snapshots()?;
+        snapshots.sort_by_key(|s| (s.timestamp, s.id));
+
+        let mut files = 0;
+        let mut logical_bytes = 0;
+        let mut content_sizes: HashMap<String, u64> = HashMap::new();
+        let mut content_dirs: HashMap<String, HashSet<String>> = HashMap::new();
+        let mut latest_by_target: HashMap<String, (u32, HashSet<String>)> = HashMap::new();
+        let mut snapshot_stats = Vec::new();
+
+        for snapshot in &snapshots {
+            let snapshot_files = self.db.get_snapshot_files(snapshot.id)?;
+            let parent = latest_by_target.get(&snapshot.target_directory);
+
+            let mut contents = HashSet::new();
+            let mut added_bytes = 0;
+            for file in &snapshot_files {
+                if contents.insert(file.content_hash.clone())
+                    && !parent.is_some_and(|(_, parent_contents)| parent_contents.contains(&file.content_hash))
+                {
+                    added_bytes += file.size;
+                }
+                content_sizes.insert(file.content_hash.clone(), file.size);
+                content_dirs.entry(file.content_hash.clone()).or_default().insert(directory_of(&file.path));
+            }
+
+            let total_size: u64 = snapshot_files.iter().map(|f| f.size).sum();
+            files += snapshot_files.len() as u64;
+            logical_bytes += total_size;
+            snapshot_stats.push(SnapshotStats {
+                snapshot_id: snapshot.id,
+                parent: parent.map(|(id, _)| *id),
+                files: snapshot_files.len() as u64,
+                total_size,
+                added_bytes,
+            });
+            latest_by_target.insert(snapshot.target_directory.clone(), (snapshot.id, contents));
+        }
+        snapshot_stats.sort_by_key(|s| s.snapshot_id);
+
+        let mut directory_bytes: HashMap<String, u64> = HashMap::new();
+        for (hash, dirs) in &content_dirs {
+            if let [dir] = dirs.iter().collect::<Vec<_>>().as_slice() {
+                *directory_bytes.entry(dir.to_string()).or_default() += content_sizes[hash];
+            }
+        }
+        let mut directories: Vec<DirectoryStats> = directory_bytes.into_iter()
+            .map(|(path, unique_bytes)| DirectoryStats { path, unique_bytes })
+            .collect();
+        directories.sort_by(|a, b| b.unique_bytes.cmp(&a.unique_bytes).then_with(|| a.path.cmp(&b.path)));
+        directories.truncate(top_directories);
+
+        Ok(StatsReport {
+            snapshots: snapshots.len() as u64,
+            forgotten_snapshots: self.db.list_forgotten_snapshots()?.len() as u64,
+            files,
+            distinct_contents: content_sizes.len() as u64,
+            logical_bytes,
+            unique_bytes,
+            stored_bytes: totals.stored_bytes,
+            dedup_ratio: if unique_bytes == 0 { 1.0 } else { logical_bytes as f64 / unique_bytes as f64 },
+            database_size: self.db.file_size()?,
+            snapshot_stats,
+            top_directories: directories,
+        })
+    }
+}
+
+/// The directory a snapshot path lives in, `.` for the snapshot root.
+fn directory_of(path: &str) -> String {
+    match Path::new(path).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_string_lossy().to_string(),
+        _ => ".".to_string(),
+    }
+}
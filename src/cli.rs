@@ -1,85 +1,1011 @@
-use clap::{Parser, Subcommand};
-use std::path::PathBuf;
+use clap::{Parser, Subcommand, ValueEnum};
+use std::fs;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::str::FromStr;
+use chrono::{TimeDelta, Utc};
 use anyhow::Result;
+use serde::Serialize;
 
-use crate::storage::Database;
-use crate::backup::{Snapshot, Restore, Prune};
+use crate::error::Error;
+use crate::config::{Config, RetentionPolicy};
+use crate::storage::{Database, S3Store, SnapshotSelector, RepositoryLock, LockKind, LockInfo};
+use crate::storage::lock::{list_locks, remove_lock};
+use crate::storage::selector::{short_id, snapshot_number};
+use crate::storage::database::{SnapshotInfo, ForgottenSnapshot, SizeChange, RepositoryInfo};
+use crate::backup::{Transfer, Merge, Check, Snapshot, Restore, Prune, PruneScope, PruneSelection, Remote, Export, ExportFormat, Stats, StatsReport};
+use crate::backup::{Progress, NoProgress, TerminalProgress, LogProgress};
+use crate::backup::{SnapshotSummary, RestoreReport, PruneReport, RetentionReport, ForgetReport, GcReport, PushReport, PullReport, ExportReport, CopyReport, MergeReport, CheckReport};
 
 #[derive(Parser)]
 #[command(name = "backuptool")]
 #[command(about = "A command line file backup tool with incremental snapshots")]
 #[command(version = "0.1.0")]
 pub struct Cli {
+    /// Print machine-readable JSON instead of text
+    #[arg(long = "json", global = true)]
+    pub json: bool,
+    /// How to report progress of snapshots and restores
+    #[arg(long = "progress", global = true, value_enum, default_value = "auto")]
+    pub progress: ProgressMode,
+    /// Seconds between progress lines in log mode
+    #[arg(long = "progress-interval", global = true, default_value = "10")]
+    pub progress_interval: u64,
+    /// Repository database file (default: $BACKUPTOOL_REPOSITORY, the config's default repository, then ./backups.db)
+    #[arg(long = "database", global = true)]
+    pub database: Option<PathBuf>,
+    /// Name of a repository defined in the config file
+    #[arg(long = "repository", global = true, conflicts_with = "database")]
+    pub repository: Option<String>,
+    /// Config file to read instead of ~/.config/backuptool/config.toml
+    #[arg(long = "config", global = true)]
+    pub config: Option<PathBuf>,
+    /// Seconds to wait for another process to release the repository lock
+    #[arg(long = "lock-wait", global = true, default_value = "0")]
+    pub lock_wait: u64,
     #[command(subcommand)]
     pub command: Commands,
 }
 
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ProgressMode {
+    /// Progress bar when stderr is a terminal, periodic log lines otherwise
+    Auto,
+    /// Progress bar redrawn in place
+    Bar,
+    /// Periodic progress lines
+    Log,
+    /// No progress output
+    None,
+}
+
 #[derive(Subcommand)]
 pub enum Commands {
-    /// Takes a snapshot of all files in the specified directory
+    /// Creates a new, empty repository; other commands refuse to run without one
+    Init,
+    /// Takes a snapshot of all files in the specified directories
     Snapshot {
-        /// Directory to snapshot
-        #[arg(long = "target-directory")]
-        target_directory: PathBuf,
-        /// Optional database path (default: ./backups.db)
-        #[arg(long = "database", default_value = "backups.db")]
-        database: PathBuf,
+        /// Directory to snapshot (repeatable)
+        #[arg(long = "target-directory", required_unless_present_any = ["sources_file", "stdin"])]
+        target_directory: Vec<PathBuf>,
+        /// File listing directories to snapshot, one per line
+        #[arg(long = "sources-file")]
+        sources_file: Option<PathBuf>,
+        /// Snapshot standard input as a single file instead of directories
+        #[arg(long = "stdin", conflicts_with_all = ["target_directory", "sources_file"])]
+        stdin: bool,
+        /// Name of the file standard input is stored as
+        #[arg(long = "stdin-filename", default_value = "stdin", requires = "stdin")]
+        stdin_filename: String,
+        /// Leave out files and directories matching this glob, in addition to the configured excludes (repeatable)
+        #[arg(long = "exclude", conflicts_with = "stdin")]
+        excludes: Vec<String>,
+        /// Abort without storing anything on the first unreadable file
+        #[arg(long = "strict")]
+        strict: bool,
+        /// Tag to attach to the snapshot (repeatable)
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+        /// Description stored with the snapshot
+        #[arg(long = "message")]
+        message: Option<String>,
+    },
+    /// Stores the files of a tar archive (.tar, .tar.gz or .tar.zst) as a new snapshot
+    Import {
+        /// Archive to import; compression is detected from its contents
+        #[arg(long = "archive")]
+        archive: PathBuf,
+        /// Abort without storing anything on the first entry that cannot be imported
+        #[arg(long = "strict")]
+        strict: bool,
+        /// Tag to attach to the snapshot (repeatable)
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+        /// Description stored with the snapshot
+        #[arg(long = "message")]
+        message: Option<String>,
+    },
+    /// Writes one file of a snapshot to standard output
+    Cat {
+        /// Snapshot to read from (number or selector)
+        #[arg(long = "snapshot")]
+        snapshot: SnapshotSelector,
+        /// Path of the file within the snapshot
+        path: String,
+    },
+    /// Writes a snapshot as a tar or zip archive
+    Export {
+        /// Snapshot to export (number or selector)
+        #[arg(long = "snapshot")]
+        snapshot: SnapshotSelector,
+        /// Archive format: tar, tar.zst or zip
+        #[arg(long = "format")]
+        format: ExportFormat,
+        /// Archive file to write, or - for standard output (tar formats only)
+        #[arg(long = "output")]
+        output: PathBuf,
     },
     /// Lists snapshots stored in the database
     List {
-        /// Optional database path (default: ./backups.db)
-        #[arg(long = "database", default_value = "backups.db")]
-        database: PathBuf,
+        /// Only list snapshots carrying this tag (repeatable, all must match)
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+        /// List forgotten snapshots that can still be undeleted instead
+        #[arg(long = "deleted", conflicts_with = "tags")]
+        deleted: bool,
+    },
+    /// Shows how much data the repository holds and how well it deduplicates
+    Stats {
+        /// Number of directories to list by unique storage
+        #[arg(long = "top", default_value = "10")]
+        top: usize,
     },
     /// Restores directory state from a snapshot
     Restore {
-        /// Snapshot number to restore
-        #[arg(long = "snapshot-number")]
-        snapshot_number: u32,
+        /// Snapshot to restore (number, latest, latest~N, @TIME, tag:NAME or path:DIR)
+        #[arg(long = "snapshot-number", required_unless_present_any = ["retry_failed", "tags"])]
+        snapshot_number: Option<SnapshotSelector>,
+        /// Restore the newest snapshot carrying this tag (repeatable, all must match)
+        #[arg(long = "tag", conflicts_with_all = ["snapshot_number", "retry_failed"])]
+        tags: Vec<String>,
         /// Output directory for restored files
-        #[arg(long = "output-directory")]
-        output_directory: PathBuf,
-        /// Optional database path (default: ./backups.db)
-        #[arg(long = "database", default_value = "backups.db")]
-        database: PathBuf,
+        #[arg(long = "output-directory", required_unless_present = "retry_failed")]
+        output_directory: Option<PathBuf>,
+        /// Restore remaining files when one fails (the command still exits non-zero)
+        #[arg(long = "continue-on-error")]
+        continue_on_error: bool,
+        /// Write the restore report as JSON to this file
+        #[arg(long = "report")]
+        report: Option<PathBuf>,
+        /// Restore only the files that failed in a report written by --report
+        #[arg(long = "retry-failed")]
+        retry_failed: Option<PathBuf>,
     },
-    /// Removes old snapshots and unreferenced data
+    /// Forgets old snapshots until gc removes them, or deletes them with --now; without a selection the configured retention policy applies
     Prune {
-        /// Snapshot number to prune
+        /// Snapshots to prune: numbers, selectors or ranges like 10-20 (comma separated, repeatable)
+        #[arg(long = "snapshot", value_delimiter = ',', conflicts_with = "keep_last")]
+        snapshots: Vec<SnapshotSpec>,
+        /// Keep only the newest N snapshots and prune the older ones
+        #[arg(long = "keep-last", conflicts_with = "older_than")]
+        keep_last: Option<usize>,
+        /// Prune snapshots older than this age, e.g. 36h, 90d or 8w
+        #[arg(long = "older-than", value_parser = parse_age)]
+        older_than: Option<TimeDelta>,
+        /// Only prune snapshots carrying this tag (repeatable, all must match)
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+        /// Only prune snapshots that include this source directory
+        #[arg(long = "target-directory")]
+        target_directory: Option<String>,
+        /// Delete the snapshots and their unreferenced data right away instead of forgetting them
+        #[arg(long = "now")]
+        now: bool,
+    },
+    /// Hides snapshots so that a later gc can remove them; undelete brings them back
+    Forget {
+        /// Snapshot to forget (number or selector, repeatable)
+        #[arg(long = "snapshot", required = true)]
+        snapshots: Vec<SnapshotSelector>,
+    },
+    /// Restores a forgotten snapshot that has not been garbage collected yet
+    Undelete {
+        /// Forgotten snapshot to bring back (number or selector, matched among forgotten snapshots; see list --deleted)
         #[arg(long = "snapshot")]
-        snapshot: u32,
-        /// Optional database path (default: ./backups.db)
-        #[arg(long = "database", default_value = "backups.db")]
-        database: PathBuf,
+        snapshot: SnapshotSelector,
     },
+    /// Removes snapshots forgotten longer ago than the grace period and all unreferenced data
+    Gc {
+        /// Days a forgotten snapshot can still be undeleted
+        #[arg(long = "grace-days", default_value = "7")]
+        grace_days: u32,
+    },
+    /// Rewrites the database file without free space left behind by deletions
+    Compact,
+    /// Adds or removes tags on an existing snapshot
+    Tag {
+        #[command(subcommand)]
+        action: TagAction,
+    },
+    /// Protects a snapshot from being pruned
+    Protect {
+        /// Snapshot to protect (number or selector)
+        #[arg(long = "snapshot")]
+        snapshot: SnapshotSelector,
+    },
+    /// Allows a protected snapshot to be pruned again
+    Unprotect {
+        /// Snapshot to unprotect (number or selector)
+        #[arg(long = "snapshot")]
+        snapshot: SnapshotSelector,
+    },
+    /// Removes repository locks left behind by processes that are no longer running
+    Unlock {
+        /// Also remove locks that still look active
+        #[arg(long = "all")]
+        all: bool,
+    },
+    /// Uploads new content and the snapshot index to an S3-compatible bucket
+    Push {
+        /// Remote location (s3://bucket/prefix)
+        #[arg(long = "remote")]
+        remote: String,
+        /// Optional S3 endpoint URL (default: $AWS_ENDPOINT_URL or AWS)
+        #[arg(long = "endpoint")]
+        endpoint: Option<String>,
+    },
+    /// Downloads snapshots missing from the database from an S3-compatible bucket
+    Pull {
+        /// Remote location (s3://bucket/prefix)
+        #[arg(long = "remote")]
+        remote: String,
+        /// Optional S3 endpoint URL (default: $AWS_ENDPOINT_URL or AWS)
+        #[arg(long = "endpoint")]
+        endpoint: Option<String>,
+    },
+    /// Combines repositories into a new one, numbering snapshots in the order they were taken
+    Merge {
+        /// Repositories to merge, configured names or paths
+        #[arg(required = true, num_args = 2..)]
+        repositories: Vec<String>,
+        /// Path of the repository to create
+        #[arg(long = "output")]
+        output: PathBuf,
+    },
+    /// Copies the snapshots of one source directory into a new repository
+    Split {
+        /// Source directory whose snapshots are taken out
+        #[arg(long = "target-directory")]
+        target_directory: String,
+        /// Path of the repository to create
+        #[arg(long = "output")]
+        output: PathBuf,
+    },
+    /// Verifies the repository's structure and that all content matches its hash
+    Check,
+    /// Copies snapshots and the content they need into another repository
+    Copy {
+        /// Repository to copy from, a configured name or a path (default: the selected repository)
+        #[arg(long = "from")]
+        from: Option<String>,
+        /// Repository to copy to, a configured name or a path
+        #[arg(long = "to")]
+        to: String,
+        /// Snapshot to copy (number or selector, repeatable; default: all)
+        #[arg(long = "snapshot")]
+        snapshots: Vec<SnapshotSelector>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum TagAction {
+    /// Adds tags to a snapshot
+    Add {
+        /// Snapshot to tag (number or selector)
+        #[arg(long = "snapshot")]
+        snapshot: SnapshotSelector,
+        /// Tags to add
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+    /// Removes tags from a snapshot
+    Remove {
+        /// Snapshot to untag (number or selector)
+        #[arg(long = "snapshot")]
+        snapshot: SnapshotSelector,
+        /// Tags to remove
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+}
+
+/// One entry of `prune --snapshot`: a snapshot selector or an inclusive range of numbers.
+#[derive(Clone, Debug)]
+pub enum SnapshotSpec {
+    Selector(SnapshotSelector),
+    Range(u32, u32),
+}
+
+impl FromStr for SnapshotSpec {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Error> {
+        // Only two plain snapshot numbers make a range; dashed UUID prefixes are selectors
+        let range = s.split_once('-')
+            .and_then(|(first, last)| Some((snapshot_number(first)?, snapshot_number(last)?)));
+        match range {
+            Some((first, last)) if first <= last => Ok(SnapshotSpec::Range(first, last)),
+            Some(_) => Err(Error::InvalidInput(format!("snapshot range {:?} is backwards", s))),
+            None => Ok(SnapshotSpec::Selector(s.parse()?)),
+        }
+    }
+}
+
+/// Parses an age such as `36h`, `90d`, `8w` or `1y`.
+fn parse_age(s: &str) -> std::result::Result<TimeDelta, Error> {
+    let invalid = || Error::InvalidInput(format!("invalid age {:?} (expected a number followed by h, d, w or y)", s));
+    let (split, unit) = s.char_indices().last().ok_or_else(invalid)?;
+    // Unsigned, so that a negative age cannot select every snapshot
+    let number: u64 = s[..split].parse().map_err(|_| invalid())?;
+    let hours = match unit {
+        'h' => 1,
+        'd' => 24,
+        'w' => 24 * 7,
+        'y' => 24 * 365,
+        _ => return Err(invalid()),
+    };
+    if number == 0 {
+        return Err(Error::InvalidInput(format!("invalid age {:?} (must be greater than zero)", s)));
+    }
+    number.checked_mul(hours)
+        .and_then(|hours| i64::try_from(hours).ok())
+        .and_then(TimeDelta::try_hours)
+        .ok_or_else(invalid)
+}
+
+/// The `--keep-last` or `--older-than` equivalent of a configured retention policy.
+fn policy_selection(policy: &RetentionPolicy) -> Result<(Option<usize>, Option<TimeDelta>)> {
+    match (policy.keep_last, &policy.older_than) {
+        (Some(keep_last), None) => Ok((Some(keep_last), None)),
+        (None, Some(age)) => Ok((None, Some(parse_age(age)?))),
+        _ => Err(Error::InvalidInput(
+            "a retention policy needs exactly one of keep_last and older_than".to_string()
+        ).into()),
+    }
+}
+
+#[derive(Serialize)]
+struct TagReport {
+    snapshot_id: u32,
+    tags: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct ProtectReport {
+    snapshot_id: u32,
+    protected: bool,
+}
+
+#[derive(Serialize)]
+struct UndeleteReport {
+    snapshot_id: u32,
+}
+
+#[derive(Serialize)]
+struct UnlockReport {
+    removed: Vec<LockInfo>,
+    remaining: Vec<LockInfo>,
 }
 
 impl Cli {
     pub fn run(self) -> Result<()> {
+        let json = self.json;
+        let (progress_mode, progress_interval) = (self.progress, self.progress_interval);
+        let progress = || progress_reporter(progress_mode, progress_interval);
+        let lock_wait = Duration::from_secs(self.lock_wait);
+        let config = Config::load(self.config.as_deref())?;
+        let repository = config.repository(self.database.as_deref(), self.repository.as_deref())?;
+        let database = repository.path.clone();
+        let open_at = |path: &Path, kind| -> Result<(RepositoryLock, Database)> {
+            // Checked before locking so a mistyped path does not leave a lock directory behind
+            if !path.is_file() {
+                return Err(Error::RepositoryNotFound(path.to_path_buf()).into());
+            }
+            let lock = RepositoryLock::acquire(path, kind, lock_wait)?;
+            Ok((lock, Database::open(path)?))
+        };
+        let open = |kind| open_at(&database, kind);
         match self.command {
-            Commands::Snapshot { target_directory, database } => {
-                let db = Database::new(&database)?;
-                let snapshot = Snapshot::new(db);
-                snapshot.create(&target_directory)?;
-                println!("Snapshot created successfully");
-            }
-            Commands::List { database } => {
-                let db = Database::new(&database)?;
-                db.list_snapshots()?;
-            }
-            Commands::Restore { snapshot_number, output_directory, database } => {
-                let db = Database::new(&database)?;
-                let restore = Restore::new(db);
-                restore.restore_snapshot(snapshot_number, &output_directory)?;
-                println!("Snapshot {} restored to {}", snapshot_number, output_directory.display());
-            }
-            Commands::Prune { snapshot, database } => {
-                let db = Database::new(&database)?;
-                let prune = Prune::new(db);
-                prune.prune_snapshot(snapshot)?;
-                println!("Snapshot {} pruned successfully", snapshot);
+            Commands::Snapshot {
+                mut target_directory, sources_file, stdin, stdin_filename, excludes, strict, tags, message
+            } => {
+                if let Some(sources_file) = sources_file {
+                    target_directory.extend(read_sources_file(&sources_file)?);
+                }
+                let (_lock, db) = open(LockKind::Shared)?;
+                let snapshot = Snapshot::new(db)
+                    .with_progress(progress())
+                    .strict(strict)
+                    .with_tags(tags)
+                    .with_message(message)
+                    .with_excludes(repository.excludes.iter().cloned().chain(excludes).collect());
+                let summary = if stdin {
+                    snapshot.create_from_reader(std::io::stdin().lock(), &stdin_filename)?
+                } else {
+                    snapshot.create_from_sources(&target_directory)?
+                };
+                emit(json, &summary, print_snapshot_summary)?;
+                check_complete(&summary)?;
+            }
+            Commands::Import { archive, strict, tags, message } => {
+                let (_lock, db) = open(LockKind::Shared)?;
+                let summary = Snapshot::new(db)
+                    .with_progress(progress())
+                    .strict(strict)
+                    .with_tags(tags)
+                    .with_message(message)
+                    .create_from_archive(&archive)?;
+                emit(json, &summary, print_snapshot_summary)?;
+                check_complete(&summary)?;
+            }
+            Commands::Cat { snapshot, path } => {
+                let (_lock, db) = open(LockKind::Shared)?;
+                let snapshot_id = db.resolve_snapshot(&snapshot)?;
+                Restore::new(db).cat(snapshot_id, &path, &mut std::io::stdout().lock())?;
+            }
+            Commands::Export { snapshot, format, output } => {
+                let (_lock, db) = open(LockKind::Shared)?;
+                let snapshot_id = db.resolve_snapshot(&snapshot)?;
+                let report = Export::new(db).export_to_path(snapshot_id, format, &output)?;
+                // The archive itself went to stdout, so there is nowhere to print the report
+                if output != Path::new("-") {
+                    emit(json, &report, print_export_report)?;
+                }
+            }
+            Commands::List { deleted: true, .. } => {
+                let (_lock, db) = open(LockKind::Shared)?;
+                let snapshots = db.list_forgotten_snapshots()?;
+                emit(json, &snapshots, |snapshots| print_forgotten_list(snapshots))?;
+            }
+            Commands::List { tags, .. } => {
+                let (_lock, db) = open(LockKind::Shared)?;
+                let mut snapshots = db.list_snapshots()?;
+                snapshots.retain(|s| s.has_tags(&tags));
+                emit(json, &snapshots, |snapshots| print_snapshot_list(snapshots))?;
+            }
+            Commands::Stats { top } => {
+                let (_lock, db) = open(LockKind::Shared)?;
+                let report = Stats::new(db).report(top)?;
+                emit(json, &report, print_stats_report)?;
+            }
+            Commands::Restore {
+                snapshot_number, tags, output_directory, continue_on_error, report, retry_failed
+            } => {
+                let (_lock, db) = open(LockKind::Shared)?;
+                let previous = retry_failed.as_deref().map(read_restore_report).transpose()?;
+                let snapshot_number = match snapshot_number {
+                    Some(selector) => Some(db.resolve_snapshot(&selector)?),
+                    None if !tags.is_empty() => Some(latest_with_tags(&db, &tags)?),
+                    None => None,
+                };
+                let (snapshot_id, output_directory) = match &previous {
+                    Some(previous) => {
+                        if snapshot_number.is_some_and(|n| n != previous.snapshot_id) {
+                            return Err(Error::InvalidInput(format!(
+                                "report is for snapshot {}, not {}",
+                                previous.snapshot_id, snapshot_number.unwrap()
+                            )).into());
+                        }
+                        (previous.snapshot_id, output_directory.unwrap_or_else(|| previous.output_directory.clone()))
+                    }
+                    // clap requires both arguments when --retry-failed is absent
+                    None => (snapshot_number.unwrap(), output_directory.unwrap()),
+                };
+
+                let restore = Restore::new(db)
+                    .with_progress(progress())
+                    .continue_on_error(continue_on_error);
+                let paths: Option<Vec<String>> = previous.as_ref()
+                    .map(|previous| previous.failed_files.iter().map(|f| f.path.clone()).collect());
+                let (restore_report, stopped) = restore.restore_with_report(snapshot_id, &output_directory, paths.as_deref())?;
+
+                // Written even when the restore stopped, so --retry-failed can pick up from there
+                if let Some(report_path) = report {
+                    fs::write(&report_path, serde_json::to_vec_pretty(&restore_report)?)
+                        .map_err(|e| Error::io(&report_path, e))?;
+                }
+                if let Some(e) = stopped {
+                    return Err(e.into());
+                }
+                emit(json, &restore_report, print_restore_report)?;
+                if !restore_report.failed_files.is_empty() {
+                    return Err(Error::Incomplete(format!(
+                        "{} file(s) of snapshot {} could not be restored",
+                        restore_report.failed_files.len(), snapshot_id
+                    )).into());
+                }
+            }
+            Commands::Prune { snapshots, mut keep_last, mut older_than, mut tags, mut target_directory, now } => {
+                if snapshots.is_empty() && keep_last.is_none() && older_than.is_none() {
+                    let policy = repository.retention.clone().ok_or_else(|| Error::InvalidInput(
+                        "nothing to prune: use --snapshot, --keep-last or --older-than, or configure a retention policy".to_string()
+                    ))?;
+                    (keep_last, older_than) = policy_selection(&policy)?;
+                    tags.extend(policy.tags);
+                    target_directory = target_directory.or(policy.target_directory);
+                }
+                // Forgetting only marks snapshots, like `forget`; deleting needs the repository to itself
+                let (_lock, db) = open(if now { LockKind::Exclusive } else { LockKind::Shared })?;
+                let scoped = !tags.is_empty() || target_directory.is_some();
+                let scope = PruneScope { tags, target_directory };
+                match (snapshots.as_slice(), keep_last) {
+                    // A single snapshot on its own keeps the simpler report
+                    ([SnapshotSpec::Selector(selector)], None) if older_than.is_none() && !scoped => {
+                        let snapshot_id = db.resolve_snapshot(selector)?;
+                        let report = Prune::new(db).delete_now(now).prune_snapshot(snapshot_id)?;
+                        emit(json, &report, print_prune_report)?;
+                    }
+                    (_, Some(keep_last)) => {
+                        let report = Prune::new(db).delete_now(now).keep_last(keep_last, &scope)?;
+                        emit(json, &report, print_retention_report)?;
+                    }
+                    _ => {
+                        let mut selection = PruneSelection {
+                            older_than: older_than.map(|age| Utc::now() - age),
+                            ..PruneSelection::default()
+                        };
+                        for spec in &snapshots {
+                            match spec {
+                                SnapshotSpec::Selector(selector) => selection.ids.push(db.resolve_snapshot(selector)?),
+                                SnapshotSpec::Range(first, last) => selection.ranges.push(*first..=*last),
+                            }
+                        }
+                        let report = Prune::new(db).delete_now(now).prune_matching(&selection, &scope)?;
+                        emit(json, &report, print_retention_report)?;
+                    }
+                }
+            }
+            Commands::Forget { snapshots } => {
+                let (_lock, db) = open(LockKind::Shared)?;
+                let mut snapshot_ids = Vec::new();
+                for selector in &snapshots {
+                    let snapshot_id = db.resolve_snapshot(selector)?;
+                    if !snapshot_ids.contains(&snapshot_id) {
+                        snapshot_ids.push(snapshot_id);
+                    }
+                }
+                let report = Prune::new(db).forget(&snapshot_ids)?;
+                emit(json, &report, print_forget_report)?;
+            }
+            Commands::Undelete { snapshot } => {
+                let (_lock, db) = open(LockKind::Shared)?;
+                let snapshot_id = db.resolve_forgotten_snapshot(&snapshot)?;
+                Prune::new(db).undelete(snapshot_id)?;
+                emit(json, &UndeleteReport { snapshot_id }, |report| {
+                    println!("Snapshot {} restored", report.snapshot_id);
+                })?;
+            }
+            Commands::Gc { grace_days } => {
+                let (_lock, db) = open(LockKind::Exclusive)?;
+                let report = Prune::new(db).gc(TimeDelta::days(grace_days as i64))?;
+                emit(json, &report, print_gc_report)?;
+            }
+            Commands::Compact => {
+                let (_lock, mut db) = open(LockKind::Exclusive)?;
+                let size = db.compact()?;
+                emit(json, &size, |size| {
+                    println!("Database compacted");
+                    print_size_change(size);
+                })?;
+            }
+            Commands::Tag { action: TagAction::Add { snapshot, tags } } => {
+                let (_lock, db) = open(LockKind::Shared)?;
+                let snapshot = db.resolve_snapshot(&snapshot)?;
+                db.transaction(|| tags.iter().try_for_each(|tag| db.add_snapshot_tag(snapshot, tag).map(|_| ())))?;
+                let report = TagReport { snapshot_id: snapshot, tags: db.get_snapshot_tags(snapshot)? };
+                emit(json, &report, print_tag_report)?;
+            }
+            Commands::Tag { action: TagAction::Remove { snapshot, tags } } => {
+                let (_lock, db) = open(LockKind::Shared)?;
+                let snapshot = db.resolve_snapshot(&snapshot)?;
+                db.transaction(|| tags.iter().try_for_each(|tag| db.remove_snapshot_tag(snapshot, tag).map(|_| ())))?;
+                let report = TagReport { snapshot_id: snapshot, tags: db.get_snapshot_tags(snapshot)? };
+                emit(json, &report, print_tag_report)?;
+            }
+            Commands::Protect { snapshot } => {
+                let (_lock, db) = open(LockKind::Shared)?;
+                let snapshot = db.resolve_snapshot(&snapshot)?;
+                db.set_snapshot_protected(snapshot, true)?;
+                emit(json, &ProtectReport { snapshot_id: snapshot, protected: true }, print_protect_report)?;
+            }
+            Commands::Unprotect { snapshot } => {
+                let (_lock, db) = open(LockKind::Shared)?;
+                let snapshot = db.resolve_snapshot(&snapshot)?;
+                db.set_snapshot_protected(snapshot, false)?;
+                emit(json, &ProtectReport { snapshot_id: snapshot, protected: false }, print_protect_report)?;
+            }
+            Commands::Push { remote, endpoint } => {
+                let (_lock, db) = open(LockKind::Shared)?;
+                let store = S3Store::from_url(&remote, endpoint.as_deref())?;
+                let report = Remote::new(db, Box::new(store)).push()?;
+                emit(json, &report, print_push_report)?;
+            }
+            Commands::Pull { remote, endpoint } => {
+                let (_lock, db) = open(LockKind::Shared)?;
+                let store = S3Store::from_url(&remote, endpoint.as_deref())?;
+                let report = Remote::new(db, Box::new(store)).pull()?;
+                emit(json, &report, print_pull_report)?;
+            }
+            Commands::Copy { from, to, snapshots } => {
+                let source_path = match from {
+                    Some(from) => config.lookup(&from)?.path,
+                    None => database.clone(),
+                };
+                let (_source_lock, source) = open_at(&source_path, LockKind::Shared)?;
+                let (_destination_lock, destination) = open_at(&config.lookup(&to)?.path, LockKind::Shared)?;
+                let snapshot_ids = snapshots.iter()
+                    .map(|selector| source.resolve_snapshot(selector))
+                    .collect::<crate::error::Result<Vec<_>>>()?;
+                let report = Transfer::new(source, destination).copy(&snapshot_ids)?;
+                emit(json, &report, print_copy_report)?;
+            }
+            Commands::Merge { repositories, output } => {
+                let mut locks = Vec::new();
+                let mut inputs = Vec::new();
+                for name in repositories {
+                    let (lock, db) = open_at(&config.lookup(&name)?.path, LockKind::Shared)?;
+                    locks.push(lock);
+                    inputs.push((name, db));
+                }
+                let merge = inputs.into_iter()
+                    .fold(Merge::new(create_output(&output)?), |merge, (name, db)| merge.with_input(name, db));
+                let report = finish_output(&output, merge.run())?;
+                emit(json, &report, print_merge_report)?;
+                check_passed(&report.check)?;
+            }
+            Commands::Split { target_directory, output } => {
+                let (_lock, db) = open(LockKind::Shared)?;
+                let merge = Merge::new(create_output(&output)?)
+                    .with_input(database.display().to_string(), db)
+                    .only_target_directory(Some(target_directory));
+                let report = finish_output(&output, merge.run())?;
+                emit(json, &report, print_merge_report)?;
+                check_passed(&report.check)?;
+            }
+            Commands::Check => {
+                let (_lock, db) = open(LockKind::Shared)?;
+                let report = Check::new(db).run()?;
+                emit(json, &report, print_check_report)?;
+                check_passed(&report)?;
+            }
+            Commands::Init => {
+                let info = Database::init(&database)?.repository_info()?;
+                emit(json, &info, |info| print_repository_info(&database, info))?;
+            }
+            Commands::Unlock { all } => {
+                let mut report = UnlockReport { removed: Vec::new(), remaining: Vec::new() };
+                for found in list_locks(&database)? {
+                    if all || found.stale {
+                        remove_lock(&found)?;
+                        report.removed.push(found);
+                    } else {
+                        report.remaining.push(found);
+                    }
+                }
+                emit(json, &report, print_unlock_report)?;
             }
         }
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+fn progress_reporter(mode: ProgressMode, interval_secs: u64) -> Box<dyn Progress> {
+    let interval = Duration::from_secs(interval_secs);
+    match mode {
+        ProgressMode::Auto if std::io::stderr().is_terminal() => Box::new(TerminalProgress::new()),
+        ProgressMode::Auto | ProgressMode::Log => Box::new(LogProgress::new(interval)),
+        ProgressMode::Bar => Box::new(TerminalProgress::new()),
+        ProgressMode::None => Box::new(NoProgress),
+    }
+}
+
+/// Id of the newest snapshot carrying all of `tags`.
+fn latest_with_tags(db: &Database, tags: &[String]) -> Result<u32> {
+    let latest = db.list_snapshots()?
+        .into_iter()
+        .filter(|s| s.has_tags(tags))
+        .max_by_key(|s| (s.timestamp, s.id))
+        .ok_or_else(|| Error::InvalidInput(format!("no snapshot is tagged {}", tags.join(", "))))?;
+    Ok(latest.id)
+}
+
+/// Fails with `Incomplete` once the summary has been printed if any path was skipped.
+fn check_complete(summary: &SnapshotSummary) -> Result<()> {
+    if !summary.skipped_files.is_empty() {
+        return Err(Error::Incomplete(format!(
+            "snapshot {} is partial, {} path(s) could not be read",
+            summary.snapshot_id, summary.skipped_files.len()
+        )).into());
+    }
+    Ok(())
+}
+
+/// Creates the repository `merge` and `split` write into, which must not exist yet.
+fn create_output(output: &Path) -> Result<Database> {
+    if output.exists() {
+        return Err(Error::InvalidInput(format!("{} already exists", output.display())).into());
+    }
+    Ok(Database::init(output)?)
+}
+
+/// Removes the output of a failed `merge` or `split` so no half-written repository is left.
+fn finish_output(output: &Path, result: crate::error::Result<MergeReport>) -> Result<MergeReport> {
+    result.map_err(|e| {
+        let _ = fs::remove_file(output);
+        e.into()
+    })
+}
+
+/// Fails with `Corruption` once the report has been printed if the check found problems.
+fn check_passed(report: &CheckReport) -> Result<()> {
+    if !report.problems.is_empty() {
+        return Err(Error::Corruption(format!("integrity check found {} problem(s)", report.problems.len())).into());
+    }
+    Ok(())
+}
+
+/// Reads one directory per line, ignoring blank lines and `#` comments.
+fn read_sources_file(path: &Path) -> Result<Vec<PathBuf>> {
+    let contents = fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+    Ok(contents.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(PathBuf::from)
+        .collect())
+}
+
+fn read_restore_report(path: &Path) -> Result<RestoreReport> {
+    let data = fs::read(path).map_err(|e| Error::io(path, e))?;
+    let report = serde_json::from_slice(&data)
+        .map_err(|e| Error::InvalidInput(format!("{} is not a restore report: {}", path.display(), e)))?;
+    Ok(report)
+}
+
+/// Exit code for a failed command: the library error's code if there is one, otherwise 1.
+pub fn exit_code(error: &anyhow::Error) -> u8 {
+    error.chain()
+        .find_map(|cause| cause.downcast_ref::<Error>())
+        .map(Error::exit_code)
+        .unwrap_or(1)
+}
+
+/// Prints `value` as JSON when requested, otherwise through its text renderer.
+fn emit<T: Serialize>(json: bool, value: &T, text: impl FnOnce(&T)) -> Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(value)?);
+    } else {
+        text(value);
+    }
+    Ok(())
+}
+
+fn print_snapshot_summary(summary: &SnapshotSummary) {
+    for skipped in &summary.skipped_files {
+        eprintln!("Warning: Failed to process file {}: {}", skipped.path, skipped.error);
+    }
+    println!("Snapshot {} created successfully", summary.snapshot_id);
+    if summary.sources.len() > 1 {
+        for source in &summary.sources {
+            println!("  Source {}: {}", source.name, source.path);
+        }
+    } else {
+        println!("  Directory: {}", summary.target_directory);
+    }
+    println!("  Files processed: {}", summary.files_processed);
+    println!("  Total size: {} bytes", summary.total_size);
+    println!("  New data: {} bytes", summary.bytes_added);
+    println!("  Deduplicated files: {}", summary.deduplicated_files);
+    if !summary.tags.is_empty() {
+        println!("  Tags: {}", summary.tags.join(", "));
+    }
+}
+
+fn print_snapshot_list(snapshots: &[SnapshotInfo]) {
+    println!("SNAPSHOT  ID        TIMESTAMP            SIZE  DISTINCT_SIZE  ERRORS  PROTECTED  TAGS");
+    let mut total_db_size = 0u64;
+
+    for snapshot in snapshots {
+        total_db_size += snapshot.distinct_size;
+        println!("{:<8}  {:<8}  {:<19}  {:<4}  {:<13}  {:<6}  {:<9}  {}",
+                 snapshot.id,
+                 short_id(&snapshot.uuid),
+                 snapshot.timestamp.format("%Y-%m-%d %H:%M:%S"),
+                 snapshot.total_size,
+                 snapshot.distinct_size,
+                 snapshot.error_count,
+                 if snapshot.protected { "yes" } else { "no" },
+                 snapshot.tags.join(","));
+    }
+
+    println!("total                                    {}", total_db_size);
+}
+
+fn print_forgotten_list(snapshots: &[ForgottenSnapshot]) {
+    println!("SNAPSHOT  TIMESTAMP            FORGOTTEN            TARGET");
+    for snapshot in snapshots {
+        println!("{:<8}  {:<19}  {:<19}  {}",
+                 snapshot.id,
+                 snapshot.timestamp.format("%Y-%m-%d %H:%M:%S"),
+                 snapshot.deleted_at.format("%Y-%m-%d %H:%M:%S"),
+                 snapshot.target_directory);
+    }
+}
+
+fn print_stats_report(report: &StatsReport) {
+    println!("Snapshots: {} ({} forgotten)", report.snapshots, report.forgotten_snapshots);
+    println!("Files: {} entries, {} distinct contents", report.files, report.distinct_contents);
+    println!("Logical size: {} bytes", report.logical_bytes);
+    println!("Unique content: {} bytes", report.unique_bytes);
+    println!("Stored: {} bytes", report.stored_bytes);
+    println!("Deduplication ratio: {:.2}", report.dedup_ratio);
+    println!("Database file: {} bytes", report.database_size);
+
+    println!();
+    println!("SNAPSHOT  PARENT  FILES  SIZE  ADDED");
+    for snapshot in &report.snapshot_stats {
+        println!("{:<8}  {:<6}  {:<5}  {:<4}  {}",
+                 snapshot.snapshot_id,
+                 snapshot.parent.map(|p| p.to_string()).unwrap_or_else(|| "-".to_string()),
+                 snapshot.files,
+                 snapshot.total_size,
+                 snapshot.added_bytes);
+    }
+
+    if !report.top_directories.is_empty() {
+        println!();
+        println!("UNIQUE  DIRECTORY");
+        for directory in &report.top_directories {
+            println!("{:<6}  {}", directory.unique_bytes, directory.path);
+        }
+    }
+}
+
+fn print_restore_report(report: &RestoreReport) {
+    for failed in &report.failed_files {
+        eprintln!("Warning: Failed to restore file {}: {}", failed.path, failed.error);
+    }
+    println!("Snapshot {} restored to {}", report.snapshot_id, report.output_directory.display());
+    println!("  Files restored: {}", report.files_restored);
+    if !report.failed_files.is_empty() {
+        println!("  Files failed: {}", report.failed_files.len());
+    }
+    println!("  Total size: {} bytes", report.total_size);
+}
+
+fn print_prune_report(report: &PruneReport) {
+    if report.forgotten {
+        println!("Snapshot {} forgotten; gc deletes it after the grace period, undelete brings it back", report.snapshot_id);
+        return;
+    }
+    println!("Snapshot {} pruned successfully", report.snapshot_id);
+    println!("  Reclaimed: {} bytes", report.bytes_reclaimed);
+    println!("  Packs repacked: {}", report.packs_repacked);
+    print_size_change(&report.database_size);
+}
+
+fn print_retention_report(report: &RetentionReport) {
+    let action = if report.forgotten { "Forgot" } else { "Pruned" };
+    println!("{} {} snapshot(s), kept {}", action, report.removed.len(), report.kept.len());
+    for snapshot_id in &report.removed {
+        println!("  {} snapshot {}", action, snapshot_id);
+    }
+    if report.forgotten {
+        println!("  gc deletes them after the grace period, undelete brings them back");
+        return;
+    }
+    println!("  Reclaimed: {} bytes", report.bytes_reclaimed);
+    println!("  Packs repacked: {}", report.packs_repacked);
+    print_size_change(&report.database_size);
+}
+
+fn print_repository_info(database: &Path, info: &RepositoryInfo) {
+    println!("Initialized repository {} at {}", info.repository_id, database.display());
+    println!("  Hash: {}", info.hash_algorithm);
+    println!(
+        "  Chunker: {} (min {}, avg {}, max {} bytes)",
+        info.chunker, info.chunk_min_size, info.chunk_avg_size, info.chunk_max_size
+    );
+    println!("  Compression: {}", info.compression);
+}
+
+fn print_size_change(size: &SizeChange) {
+    println!("  Database size: {} -> {} bytes", size.size_before, size.size_after);
+}
+
+fn print_forget_report(report: &ForgetReport) {
+    for snapshot_id in &report.forgotten {
+        println!("Snapshot {} forgotten; undelete it before the next gc past its grace period", snapshot_id);
+    }
+}
+
+fn print_gc_report(report: &GcReport) {
+    println!("Garbage collection removed {} forgotten snapshot(s)", report.snapshots_removed.len());
+    for snapshot_id in &report.snapshots_removed {
+        println!("  Removed snapshot {}", snapshot_id);
+    }
+    println!("  Files removed: {}", report.files_removed);
+    println!("  Blocks removed: {}", report.blocks_removed);
+    println!("  Reclaimed: {} bytes", report.bytes_reclaimed);
+    println!("  Packs repacked: {}", report.packs_repacked);
+    print_size_change(&report.database_size);
+}
+
+fn print_protect_report(report: &ProtectReport) {
+    if report.protected {
+        println!("Snapshot {} is now protected from pruning", report.snapshot_id);
+    } else {
+        println!("Snapshot {} is no longer protected", report.snapshot_id);
+    }
+}
+
+fn print_unlock_report(report: &UnlockReport) {
+    for lock in &report.removed {
+        println!("Removed {}", lock);
+    }
+    for lock in &report.remaining {
+        println!("Kept active {} (use --all to remove it anyway)", lock);
+    }
+    if report.removed.is_empty() && report.remaining.is_empty() {
+        println!("The repository is not locked");
+    }
+}
+
+fn print_tag_report(report: &TagReport) {
+    if report.tags.is_empty() {
+        println!("Snapshot {} has no tags", report.snapshot_id);
+    } else {
+        println!("Snapshot {} tags: {}", report.snapshot_id, report.tags.join(", "));
+    }
+}
+
+fn print_export_report(report: &ExportReport) {
+    println!("Snapshot {} exported to {}", report.snapshot_id, report.output);
+    println!("  Format: {}", report.format);
+    println!("  Files exported: {}", report.files_exported);
+    println!("  Total size: {} bytes", report.total_size);
+}
+
+fn print_push_report(report: &PushReport) {
+    println!("Push completed successfully");
+    println!("  Snapshots in remote: {}", report.snapshots_in_remote);
+    println!("  Content blocks uploaded: {}", report.blocks_uploaded);
+    println!("  Packs uploaded: {}", report.packs_uploaded);
+    println!("  Bytes uploaded: {}", report.bytes_uploaded);
+}
+
+fn print_check_report(report: &CheckReport) {
+    for problem in &report.problems {
+        println!("Problem: {}", problem);
+    }
+    println!(
+        "Checked {} snapshot(s), {} file(s) and {} content block(s) ({} bytes read)",
+        report.snapshots, report.files, report.blocks, report.bytes_read
+    );
+    if report.problems.is_empty() {
+        println!("No problems found");
+    }
+}
+
+fn print_merge_report(report: &MergeReport) {
+    for merged in &report.snapshots {
+        println!("Snapshot {} of {} is now snapshot {}", merged.source_id, merged.repository, merged.snapshot_id);
+    }
+    for duplicate in &report.duplicates {
+        println!(
+            "Snapshot {} of {} is the same as snapshot {}",
+            duplicate.source_id, duplicate.repository, duplicate.snapshot_id
+        );
+    }
+    println!("  Content blocks copied: {}", report.blocks_copied);
+    println!("  Bytes copied: {}", report.bytes_copied);
+    print_check_report(&report.check);
+}
+
+fn print_copy_report(report: &CopyReport) {
+    for copied in &report.copied {
+        println!("Snapshot {} copied as snapshot {}", copied.source_id, copied.destination_id);
+    }
+    for snapshot_id in &report.skipped {
+        println!("Snapshot {} skipped, the destination already has it", snapshot_id);
+    }
+    println!("  Content blocks copied: {}", report.blocks_copied);
+    println!("  Bytes copied: {}", report.bytes_copied);
+}
+
+fn print_pull_report(report: &PullReport) {
+    println!("Pull completed successfully");
+    println!("  Snapshots pulled: {}", report.snapshots_pulled);
+    println!("  Content blocks downloaded: {}", report.blocks_downloaded);
+    println!("  Bytes downloaded: {}", report.bytes_downloaded);
+}
@@ -0,0 +1,165 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+
+use crate::error::{Error, Result};
+
+/// Environment variable naming the repository to use: a configured name or a database path.
+pub const REPOSITORY_ENV: &str = "BACKUPTOOL_REPOSITORY";
+/// Database used when neither the command line, the environment nor the config names one.
+pub const DEFAULT_DATABASE: &str = "backups.db";
+
+/// Settings read from `config.toml`.
+///
+/// ```toml
+/// default_repository = "home"
+/// excludes = ["*.tmp", ".cache"]
+///
+/// [retention]
+/// keep_last = 30
+///
+/// [repositories.home]
+/// path = "~/backups/home.db"
+/// excludes = ["target"]
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Repository used when none is named on the command line or in the environment.
+    pub default_repository: Option<String>,
+    /// Patterns excluded from snapshots of every repository.
+    pub excludes: Vec<String>,
+    /// Policy `prune` applies when no snapshots are selected explicitly.
+    pub retention: Option<RetentionPolicy>,
+    pub repositories: BTreeMap<String, RepositoryConfig>,
+    #[serde(skip)]
+    dir: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RepositoryConfig {
+    /// Database file; relative paths are relative to the config file.
+    pub path: PathBuf,
+    /// Patterns excluded in addition to the global ones.
+    #[serde(default)]
+    pub excludes: Vec<String>,
+    /// Replaces the global retention policy for this repository.
+    pub retention: Option<RetentionPolicy>,
+}
+
+/// Which snapshots `prune` removes when run without a selection.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionPolicy {
+    /// Keep only the newest N snapshots.
+    pub keep_last: Option<usize>,
+    /// Prune snapshots older than this age, e.g. `90d`.
+    pub older_than: Option<String>,
+    /// Only prune snapshots carrying all of these tags.
+    pub tags: Vec<String>,
+    /// Only prune snapshots that include this source directory.
+    pub target_directory: Option<String>,
+}
+
+/// The repository a command operates on, together with the settings that apply to it.
+#[derive(Debug)]
+pub struct Repository {
+    pub path: PathBuf,
+    pub excludes: Vec<String>,
+    pub retention: Option<RetentionPolicy>,
+}
+
+impl Config {
+    /// Reads the config file at `path`, or the default location when `path` is `None`.
+    /// A missing default config is the same as an empty one.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Config::default()),
+            },
+        };
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !required => return Ok(Config::default()),
+            Err(e) => return Err(Error::io(&path, e)),
+        };
+
+        let mut config: Config = toml::from_str(&contents)
+            .map_err(|e| Error::InvalidInput(format!("invalid config file {}: {}", path.display(), e.message())))?;
+        config.dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        if let Some(name) = &config.default_repository {
+            if !config.repositories.contains_key(name) {
+                return Err(Error::InvalidInput(format!(
+                    "default_repository {:?} is not defined in {}", name, path.display()
+                )));
+            }
+        }
+        Ok(config)
+    }
+
+    /// Picks the repository to use: an explicit `database` path, then the repository
+    /// called `name`, then `$BACKUPTOOL_REPOSITORY`, then `default_repository`, and
+    /// finally `backups.db` in the current directory.
+    pub fn repository(&self, database: Option<&Path>, name: Option<&str>) -> Result<Repository> {
+        if let Some(database) = database {
+            return Ok(self.unnamed(database.to_path_buf()));
+        }
+        if let Some(name) = name {
+            return self.named(name);
+        }
+        if let Some(value) = env::var_os(REPOSITORY_ENV).filter(|value| !value.is_empty()) {
+            return match value.to_str() {
+                Some(value) => self.lookup(value),
+                None => Ok(self.unnamed(PathBuf::from(value))),
+            };
+        }
+        match &self.default_repository {
+            Some(name) => self.named(name),
+            None => Ok(self.unnamed(PathBuf::from(DEFAULT_DATABASE))),
+        }
+    }
+
+    /// The repository called `value`, or else the database at the path `value`.
+    pub fn lookup(&self, value: &str) -> Result<Repository> {
+        if self.repositories.contains_key(value) {
+            return self.named(value);
+        }
+        Ok(self.unnamed(PathBuf::from(value)))
+    }
+
+    fn named(&self, name: &str) -> Result<Repository> {
+        let repository = self.repositories.get(name)
+            .ok_or_else(|| Error::InvalidInput(format!("no repository named {:?} is configured", name)))?;
+        Ok(Repository {
+            path: self.resolve_path(&repository.path),
+            excludes: self.excludes.iter().chain(&repository.excludes).cloned().collect(),
+            retention: repository.retention.clone().or_else(|| self.retention.clone()),
+        })
+    }
+
+    fn unnamed(&self, path: PathBuf) -> Repository {
+        Repository { path, excludes: self.excludes.clone(), retention: self.retention.clone() }
+    }
+
+    fn resolve_path(&self, path: &Path) -> PathBuf {
+        if let (Ok(rest), Some(home)) = (path.strip_prefix("~"), env::var_os("HOME")) {
+            return PathBuf::from(home).join(rest);
+        }
+        self.dir.join(path)
+    }
+}
+
+/// `$XDG_CONFIG_HOME/backuptool/config.toml`, falling back to `~/.config`.
+pub fn default_path() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("backuptool").join("config.toml"))
+}
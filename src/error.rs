@@ -0,0 +1,109 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Errors returned by the library. Each variant maps to a distinct CLI exit code.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Snapshot {0} does not exist")]
+    SnapshotNotFound(u32),
+
+    #[error("No snapshot matches {0}")]
+    NoMatchingSnapshot(String),
+
+    #[error("Content block {0} is missing from the repository")]
+    ContentNotFound(String),
+
+    #[error("Snapshot {0} is protected; unprotect it before pruning")]
+    Protected(u32),
+
+    #[error("Repository is corrupt: {0}")]
+    Corruption(String),
+
+    #[error("Repository is locked: {0}")]
+    Locked(String),
+
+    #[error("I/O error on {}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("Database schema version {found} is not supported (this build supports up to {expected})")]
+    SchemaMismatch { expected: u32, found: u32 },
+
+    #[error("No initialised repository at {}; run `backuptool init` first", .0.display())]
+    RepositoryNotFound(PathBuf),
+
+    #[error("Remote error: {0}")]
+    Remote(String),
+
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+
+    #[error("Incomplete: {0}")]
+    Incomplete(String),
+
+    #[error("Database error")]
+    Database(#[source] rusqlite::Error),
+
+    #[error("Serialization error")]
+    Serialization(#[from] serde_json::Error),
+}
+
+impl Error {
+    pub fn io(path: &Path, source: io::Error) -> Self {
+        // Readers over repository content wrap repository errors in io::Error
+        if source.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+            return *source.into_inner().unwrap().downcast::<Error>().unwrap();
+        }
+        Error::Io { path: path.to_path_buf(), source }
+    }
+
+    /// Process exit code the CLI uses for this error.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::SnapshotNotFound(_) | Error::NoMatchingSnapshot(_) => 3,
+            Error::ContentNotFound(_) => 4,
+            Error::Corruption(_) => 5,
+            Error::Locked(_) => 6,
+            Error::Io { .. } => 7,
+            Error::SchemaMismatch { .. } => 8,
+            Error::Remote(_) => 9,
+            Error::InvalidInput(_) => 2,
+            Error::Incomplete(_) => 10,
+            Error::Protected(_) => 11,
+            Error::RepositoryNotFound(_) => 12,
+            Error::Database(_) | Error::Serialization(_) => 1,
+        }
+    }
+
+    /// Renders the error together with its underlying causes on one line.
+    pub fn describe(&self) -> String {
+        let mut message = self.to_string();
+        let mut source = std::error::Error::source(self);
+        while let Some(cause) = source {
+            message.push_str(": ");
+            message.push_str(&cause.to_string());
+            source = cause.source();
+        }
+        message
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(error: rusqlite::Error) -> Self {
+        match error.sqlite_error_code() {
+            Some(rusqlite::ErrorCode::DatabaseBusy) | Some(rusqlite::ErrorCode::DatabaseLocked) => {
+                Error::Locked("the database is in use by another process".to_string())
+            }
+            Some(rusqlite::ErrorCode::DatabaseCorrupt) | Some(rusqlite::ErrorCode::NotADatabase) => {
+                Error::Corruption(error.to_string())
+            }
+            _ => Error::Database(error),
+        }
+    }
+}
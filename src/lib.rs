@@ -1,18 +1,55 @@
 pub mod cli;
+pub mod config;
+pub mod error;
 pub mod storage;
 pub mod backup;
 pub mod utils;
 
 pub use cli::Cli;
-pub use storage::Database;
-pub use backup::{Snapshot, Restore, Prune};
+pub use error::{Error, Result};
+pub use config::{Config, Repository, RetentionPolicy};
+pub use storage::{Database, SnapshotSelector, SnapshotFilter, RepositoryLock, LockKind, LockInfo};
+pub use storage::database::{SnapshotInfo, FileInfo, RepositoryInfo};
+pub use backup::{Snapshot, SnapshotSummary, Restore, RestoreReport, Prune, PruneReport, RetentionReport, ForgetReport, GcReport, FileError};
+pub use backup::{Remote, PushReport, PullReport};
+pub use backup::{Export, ExportFormat, ExportReport};
+pub use backup::{Transfer, CopyReport, Merge, MergeReport, Check, CheckReport};
+pub use backup::{Stats, StatsReport};
 pub use utils::hash_content;
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
     use tempfile::TempDir;
 
+    #[derive(Default)]
+    struct RecordingProgress {
+        expected: Cell<(u64, u64)>,
+        files: Cell<u64>,
+        bytes: Cell<u64>,
+        finished: Cell<bool>,
+    }
+
+    impl backup::Progress for Rc<RecordingProgress> {
+        fn start(&self, total_files: u64, total_bytes: u64) {
+            self.expected.set((total_files, total_bytes));
+        }
+
+        fn bytes_processed(&self, bytes: u64) {
+            self.bytes.set(self.bytes.get() + bytes);
+        }
+
+        fn file_finished(&self, _path: &str) {
+            self.files.set(self.files.get() + 1);
+        }
+
+        fn finish(&self) {
+            self.finished.set(true);
+        }
+    }
+
     #[test]
     fn test_hash_content_consistency() {
         let content = b"Hello, World!";
@@ -66,9 +103,155 @@ mod tests {
         
         // Store content
         db.store_content(hash, content).unwrap();
+        db.flush_packs().unwrap();
         
         // Retrieve content
         let retrieved = db.get_file_content(hash).unwrap();
         assert_eq!(retrieved, content, "Retrieved content should match original");
     }
+
+    #[test]
+    fn test_small_content_is_packed_and_readable_after_flush() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let db = Database::new(&db_path).unwrap();
+
+        let small = b"small blob";
+        let large = vec![7u8; storage::pack::PACK_BLOB_THRESHOLD];
+        let small_hash = hash_content(small);
+        let large_hash = hash_content(&large);
+
+        assert!(!db.store_content(&small_hash, small).unwrap());
+        assert!(!db.store_content(&large_hash, &large).unwrap());
+        assert!(db.store_content(&small_hash, small).unwrap(), "Pending blobs should deduplicate");
+        db.flush_packs().unwrap();
+
+        let reopened = Database::new(&db_path).unwrap();
+        assert_eq!(reopened.get_file_content(&small_hash).unwrap(), small);
+        assert_eq!(reopened.get_file_content(&large_hash).unwrap(), large);
+    }
+
+    #[test]
+    fn test_pending_content_is_written_on_drop() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let small = b"stored outside a transaction";
+        let small_hash = hash_content(small);
+
+        let db = Database::new(&db_path).unwrap();
+        db.store_content(&small_hash, small).unwrap();
+        drop(db);
+
+        let reopened = Database::new(&db_path).unwrap();
+        assert_eq!(reopened.get_file_content(&small_hash).unwrap(), small);
+    }
+
+    #[test]
+    fn test_library_operations_return_reports() {
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("source");
+        std::fs::create_dir_all(&source_dir).unwrap();
+        std::fs::write(source_dir.join("a.txt"), "same").unwrap();
+        std::fs::write(source_dir.join("b.txt"), "same").unwrap();
+        let db_path = temp_dir.path().join("test.db");
+
+        let summary = Snapshot::new(Database::new(&db_path).unwrap()).create(&source_dir).unwrap();
+        assert_eq!(summary.snapshot_id, 1);
+        assert_eq!(summary.files_processed, 2);
+        assert_eq!(summary.total_size, 8);
+        assert_eq!(summary.deduplicated_files, 1);
+        assert!(summary.skipped_files.is_empty());
+
+        let snapshots = Database::new(&db_path).unwrap().list_snapshots().unwrap();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].total_size, 8);
+
+        let output_dir = temp_dir.path().join("restored");
+        let report = Restore::new(Database::new(&db_path).unwrap())
+            .restore_snapshot(1, &output_dir)
+            .unwrap();
+        assert_eq!(report.files_restored, 2);
+        assert!(report.failed_files.is_empty());
+
+        let report = Prune::new(Database::new(&db_path).unwrap()).prune_snapshot(1).unwrap();
+        assert_eq!(report.snapshot_id, 1);
+        assert!(Database::new(&db_path).unwrap().list_snapshots().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_snapshot_selector_parsing() {
+        assert_eq!("7".parse::<SnapshotSelector>().unwrap(), SnapshotSelector::Id(7));
+        assert_eq!(
+            "latest~2".parse::<SnapshotSelector>().unwrap(),
+            SnapshotSelector::Latest { filter: SnapshotFilter::All, skip: 2 }
+        );
+        assert_eq!(
+            "tag:pre-upgrade".parse::<SnapshotSelector>().unwrap(),
+            SnapshotSelector::Latest { filter: SnapshotFilter::Tag("pre-upgrade".to_string()), skip: 0 }
+        );
+        assert_eq!(
+            "path:/srv/data:latest~1".parse::<SnapshotSelector>().unwrap(),
+            SnapshotSelector::Latest { filter: SnapshotFilter::Path("/srv/data".to_string()), skip: 1 }
+        );
+
+        assert_eq!(
+            "3F2A-1b4c".parse::<SnapshotSelector>().unwrap(),
+            SnapshotSelector::Uuid("3f2a1b4c".to_string())
+        );
+        assert_eq!("uuid:1234".parse::<SnapshotSelector>().unwrap(), SnapshotSelector::Uuid("1234".to_string()));
+        // Short IDs are never read as snapshot numbers, even when they are all digits
+        assert_eq!("00000012".parse::<SnapshotSelector>().unwrap(), SnapshotSelector::Uuid("00000012".to_string()));
+        assert_eq!("12345678".parse::<SnapshotSelector>().unwrap(), SnapshotSelector::Uuid("12345678".to_string()));
+        assert_eq!("0123".parse::<SnapshotSelector>().unwrap(), SnapshotSelector::Uuid("0123".to_string()));
+        assert_eq!("1234567".parse::<SnapshotSelector>().unwrap(), SnapshotSelector::Id(1234567));
+
+        let selector = "path:/srv/data:@2026-10-01T00:00".parse::<SnapshotSelector>().unwrap();
+        let time = chrono::DateTime::parse_from_rfc3339("2026-10-01T00:00:00Z").unwrap().with_timezone(&chrono::Utc);
+        assert_eq!(selector, SnapshotSelector::AtOrBefore { filter: SnapshotFilter::Path("/srv/data".to_string()), time });
+
+        for invalid in ["", "newest", "latest~", "latest~x", "@yesterday", "tag:", "abc", "uuid:xyz1"] {
+            assert!(matches!(invalid.parse::<SnapshotSelector>(), Err(Error::InvalidInput(_))), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_missing_snapshot_is_a_typed_error() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+
+        let result = Restore::new(Database::new(&db_path).unwrap())
+            .restore_snapshot(42, &temp_dir.path().join("out"));
+        assert!(matches!(result, Err(Error::SnapshotNotFound(42))));
+
+        let result = Database::new(&db_path).unwrap().get_file_content("deadbeef");
+        assert!(matches!(result, Err(Error::ContentNotFound(_))));
+    }
+
+    #[test]
+    fn test_progress_observer_sees_files_and_bytes() {
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("source");
+        std::fs::create_dir_all(&source_dir).unwrap();
+        std::fs::write(source_dir.join("a.txt"), "12345").unwrap();
+        std::fs::write(source_dir.join("b.txt"), "678").unwrap();
+        let db_path = temp_dir.path().join("test.db");
+
+        let progress = Rc::new(RecordingProgress::default());
+        Snapshot::new(Database::new(&db_path).unwrap())
+            .with_progress(Box::new(progress.clone()))
+            .create(&source_dir)
+            .unwrap();
+        assert_eq!(progress.expected.get(), (2, 8));
+        assert_eq!(progress.files.get(), 2);
+        assert_eq!(progress.bytes.get(), 8);
+        assert!(progress.finished.get());
+
+        let progress = Rc::new(RecordingProgress::default());
+        Restore::new(Database::new(&db_path).unwrap())
+            .with_progress(Box::new(progress.clone()))
+            .restore_snapshot(1, &temp_dir.path().join("out"))
+            .unwrap();
+        assert_eq!(progress.expected.get(), (2, 8));
+        assert_eq!(progress.bytes.get(), 8);
+    }
 }
\ No newline at end of file
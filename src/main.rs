@@ -1,13 +1,15 @@
-mod cli;
-mod storage;
-mod backup;
-mod utils;
-
-use anyhow::Result;
-use cli::Cli;
+use std::process::ExitCode;
+use backuptool::Cli;
+use backuptool::cli::exit_code;
 use clap::Parser;
 
-fn main() -> Result<()> {
+fn main() -> ExitCode {
     let cli = Cli::parse();
-    cli.run()
-}
\ No newline at end of file
+    match cli.run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            ExitCode::from(exit_code(&e))
+        }
+    }
+}
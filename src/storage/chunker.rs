@@ -0,0 +1,91 @@
+use std::io::{self, Read};
+use std::mem;
+
+/// Chunks are never cut before this many bytes, except at the end of the stream.
+pub const MIN_CHUNK_SIZE: usize = 256 * 1024;
+/// Chunks are always cut at this many bytes.
+pub const MAX_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+/// A boundary is found on average once every this many bytes past the minimum.
+pub const AVG_CHUNK_SIZE: usize = 1024 * 1024;
+const BOUNDARY_MASK: u64 = AVG_CHUNK_SIZE as u64 - 1;
+
+const READ_SIZE: usize = 1024 * 1024;
+
+/// Random values for the gear rolling hash, generated with splitmix64 so they never change.
+const GEAR: [u64; 256] = gear_table();
+
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0x6261636b7570746f;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+/// Length of the first chunk in `data`. Boundaries depend only on the content around them,
+/// so an insertion early in a stream only changes the chunks near it.
+fn cut_point(data: &[u8]) -> usize {
+    if data.len() <= MIN_CHUNK_SIZE {
+        return data.len();
+    }
+
+    let end = data.len().min(MAX_CHUNK_SIZE);
+    let mut hash: u64 = 0;
+    for (i, &byte) in data.iter().enumerate().take(end).skip(MIN_CHUNK_SIZE) {
+        hash = (hash << 1).wrapping_add(GEAR[byte as usize]);
+        if hash & BOUNDARY_MASK == 0 {
+            return i + 1;
+        }
+    }
+    end
+}
+
+/// Splits a stream into content-defined chunks without holding more than one maximum-size
+/// chunk in memory.
+pub struct Chunker<R> {
+    reader: R,
+    buffer: Vec<u8>,
+    eof: bool,
+}
+
+impl<R: Read> Chunker<R> {
+    pub fn new(reader: R) -> Self {
+        Chunker { reader, buffer: Vec::new(), eof: false }
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        let mut block = vec![0u8; READ_SIZE];
+        while !self.eof && self.buffer.len() < MAX_CHUNK_SIZE {
+            match self.reader.read(&mut block) {
+                Ok(0) => self.eof = true,
+                Ok(read) => self.buffer.extend_from_slice(&block[..read]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for Chunker<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Err(e) = self.fill() {
+            return Some(Err(e));
+        }
+        if self.buffer.is_empty() {
+            return None;
+        }
+
+        let rest = self.buffer.split_off(cut_point(&self.buffer));
+        Some(Ok(mem::replace(&mut self.buffer, rest)))
+    }
+}
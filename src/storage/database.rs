@@ -1,37 +1,312 @@
-use rusqlite::{Connection, params};
-use std::path::Path;
+use rusqlite::{Connection, DatabaseName, OptionalExtension, params};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::fs;
+use std::path::{Path, PathBuf};
 use chrono::{DateTime, Utc};
-use anyhow::{Result, Context};
+use serde::{Serialize, Deserialize};
+
+use super::chunker::{MIN_CHUNK_SIZE, AVG_CHUNK_SIZE, MAX_CHUNK_SIZE};
+use super::pack::{PackBuilder, PACK_BLOB_THRESHOLD, REPACK_LIVE_RATIO};
+use super::selector::SnapshotSelector;
+use crate::error::{Error, Result};
+
+/// Version stored in `PRAGMA user_version`; databases written by newer builds are refused.
+pub const SCHEMA_VERSION: u32 = 1;
+const HASH_ALGORITHM: &str = "sha256";
+const CHUNKER: &str = "gear";
+/// Blocks are stored as they are.
+const COMPRESSION: &str = "none";
 
 pub struct Database {
     conn: Connection,
+    pending_pack: RefCell<PackBuilder>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct SnapshotInfo {
     pub id: u32,
+    /// Stable across repositories; any unique prefix selects the snapshot.
+    pub uuid: String,
     pub timestamp: DateTime<Utc>,
     pub total_size: u64,
     pub distinct_size: u64,
+    pub error_count: u64,
+    pub message: Option<String>,
+    pub tags: Vec<String>,
+    pub protected: bool,
+}
+
+impl SnapshotInfo {
+    /// Whether the snapshot carries every one of `tags` (trivially true for no tags).
+    pub fn has_tags(&self, tags: &[String]) -> bool {
+        tags.iter().all(|tag| self.tags.contains(tag))
+    }
+}
+
+#[derive(Debug)]
+pub struct SnapshotRecord {
+    pub id: u32,
+    pub timestamp: DateTime<Utc>,
+    pub target_directory: String,
+    pub message: Option<String>,
+    pub tags: Vec<String>,
+    pub protected: bool,
+    pub sources: Vec<SnapshotSource>,
+    pub uuid: String,
+}
+
+/// A directory backed up by a snapshot. When a snapshot has several sources, the paths of
+/// its files start with the source's `name`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotSource {
+    pub name: String,
+    pub path: String,
+}
+
+impl SnapshotSource {
+    /// Source named after the last component of `path`, or `root` for the filesystem root.
+    pub fn from_path(path: &str) -> Self {
+        let name = Path::new(path).file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| "root".to_string());
+        SnapshotSource { name, path: path.to_string() }
+    }
+}
+
+/// A snapshot hidden by `forget` that `gc` has not removed yet.
+#[derive(Debug, Serialize)]
+pub struct ForgottenSnapshot {
+    pub id: u32,
+    pub timestamp: DateTime<Utc>,
+    pub target_directory: String,
+    pub deleted_at: DateTime<Utc>,
+}
+
+/// Size of the database file before and after an operation that frees space.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct SizeChange {
+    pub size_before: u64,
+    pub size_after: u64,
+}
+
+/// See `Database::storage_totals`.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct StorageTotals {
+    pub blocks: u64,
+    /// Bytes of distinct content; chunked content counts through its chunks only.
+    pub unique_bytes: u64,
+    /// Bytes held in blocks and packs, including pack space not yet repacked.
+    pub stored_bytes: u64,
+}
+
+/// What `cleanup_orphaned_content` removed.
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct CleanupStats {
+    pub files_removed: u64,
+    pub blocks_removed: u64,
+    /// Bytes of stored content that were deleted.
+    pub bytes_reclaimed: u64,
+}
+
+/// Settings fixed when a repository is initialised, kept in its `config` table.
+#[derive(Debug, Clone, Serialize)]
+pub struct RepositoryInfo {
+    pub repository_id: String,
+    pub created_at: DateTime<Utc>,
+    pub hash_algorithm: String,
+    pub chunker: String,
+    pub chunk_min_size: u64,
+    pub chunk_avg_size: u64,
+    pub chunk_max_size: u64,
+    pub compression: String,
+}
+
+fn new_snapshot_uuid() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+impl RepositoryInfo {
+    /// Settings for a new repository written by this build, with a fresh random ID.
+    fn generate() -> Self {
+        RepositoryInfo {
+            repository_id: uuid::Uuid::new_v4().to_string(),
+            created_at: Utc::now(),
+            hash_algorithm: HASH_ALGORITHM.to_string(),
+            chunker: CHUNKER.to_string(),
+            chunk_min_size: MIN_CHUNK_SIZE as u64,
+            chunk_avg_size: AVG_CHUNK_SIZE as u64,
+            chunk_max_size: MAX_CHUNK_SIZE as u64,
+            compression: COMPRESSION.to_string(),
+        }
+    }
+
+    /// Content in a repository hashed or compressed differently could not be read back.
+    fn check_supported(&self) -> Result<()> {
+        if self.hash_algorithm != HASH_ALGORITHM || self.compression != COMPRESSION {
+            return Err(Error::InvalidInput(format!(
+                "repository uses {} hashing and {} compression, which this build does not support",
+                self.hash_algorithm, self.compression
+            )));
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
 pub struct FileInfo {
     pub path: String,
     pub content_hash: String,
+    pub size: u64,
+    /// Unix permission bits, when the source recorded them (archive imports do).
+    pub mode: Option<u32>,
+    /// Modification time in seconds since the epoch, when the source recorded it.
+    pub mtime: Option<i64>,
 }
 
 impl Database {
+    /// Opens the repository at `db_path`, creating and initialising it if needed. The CLI
+    /// uses `open` and `init` instead, so that a mistyped path is an error.
     pub fn new(db_path: &Path) -> Result<Self> {
-        let conn = Connection::open(db_path)
-            .context("Failed to open database connection")?;
-        
-        let db = Database { conn };
-        db.create_tables()?;
+        let db = Self::connect(db_path)?;
+        db.migrate()?;
+        if db.read_repository_info()?.is_none() {
+            db.write_repository_info(&RepositoryInfo::generate())?;
+        }
+        Ok(db)
+    }
+
+    /// Opens an existing repository, refusing paths that `init` has not set up.
+    pub fn open(db_path: &Path) -> Result<Self> {
+        if !db_path.is_file() {
+            return Err(Error::RepositoryNotFound(db_path.to_path_buf()));
+        }
+        let db = Self::connect(db_path)?;
+        let info = db.read_repository_info()?
+            .ok_or_else(|| Error::RepositoryNotFound(db_path.to_path_buf()))?;
+        info.check_supported()?;
+        db.migrate()?;
+        Ok(db)
+    }
+
+    /// Creates a repository at `db_path` and records its settings. A database written by a
+    /// build without `init` is adopted as it is; anything else already at the path is refused.
+    pub fn init(db_path: &Path) -> Result<Self> {
+        let existed = db_path.exists();
+        let db = Self::connect(db_path)?;
+        if existed {
+            if db.read_repository_info()?.is_some() {
+                return Err(Error::InvalidInput(format!("{} is already a repository", db_path.display())));
+            }
+            if !db.table_exists("snapshots")? {
+                return Err(Error::InvalidInput(format!("{} exists and is not a backuptool database", db_path.display())));
+            }
+        }
+        db.migrate()?;
+        db.write_repository_info(&RepositoryInfo::generate())?;
+        Ok(db)
+    }
+
+    fn connect(db_path: &Path) -> Result<Self> {
+        let conn = open_connection(db_path)?;
+        let db = Database { conn, pending_pack: RefCell::new(PackBuilder::default()) };
+        db.check_schema_version()?;
         Ok(db)
     }
 
+    fn migrate(&self) -> Result<()> {
+        // Only takes effect for new databases; existing ones switch over when compacted
+        self.conn.pragma_update(None, "auto_vacuum", "INCREMENTAL")?;
+        self.create_tables()?;
+        self.conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        Ok(())
+    }
+
+    /// The settings recorded when the repository was initialised.
+    pub fn repository_info(&self) -> Result<RepositoryInfo> {
+        self.read_repository_info()?
+            .ok_or_else(|| Error::Corruption("repository settings are missing".to_string()))
+    }
+
+    fn read_repository_info(&self) -> Result<Option<RepositoryInfo>> {
+        if !self.table_exists("config")? {
+            return Ok(None);
+        }
+        let mut stmt = self.conn.prepare("SELECT key, value FROM config")?;
+        let values: HashMap<String, String> = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        if !values.contains_key("repository_id") {
+            return Ok(None);
+        }
+
+        let value = |key: &str| values.get(key).cloned()
+            .ok_or_else(|| Error::Corruption(format!("repository setting {} is missing", key)));
+        let number = |key: &str| value(key)?.parse::<u64>()
+            .map_err(|_| Error::Corruption(format!("repository setting {} is not a number", key)));
+        let created_at = DateTime::parse_from_rfc3339(&value("created_at")?)
+            .map_err(|_| Error::Corruption("repository setting created_at is not a timestamp".to_string()))?
+            .with_timezone(&Utc);
+        Ok(Some(RepositoryInfo {
+            repository_id: value("repository_id")?,
+            created_at,
+            hash_algorithm: value("hash_algorithm")?,
+            chunker: value("chunker")?,
+            chunk_min_size: number("chunk_min_size")?,
+            chunk_avg_size: number("chunk_avg_size")?,
+            chunk_max_size: number("chunk_max_size")?,
+            compression: value("compression")?,
+        }))
+    }
+
+    fn write_repository_info(&self, info: &RepositoryInfo) -> Result<()> {
+        let values = [
+            ("repository_id", info.repository_id.clone()),
+            ("created_at", info.created_at.to_rfc3339()),
+            ("hash_algorithm", info.hash_algorithm.clone()),
+            ("chunker", info.chunker.clone()),
+            ("chunk_min_size", info.chunk_min_size.to_string()),
+            ("chunk_avg_size", info.chunk_avg_size.to_string()),
+            ("chunk_max_size", info.chunk_max_size.to_string()),
+            ("compression", info.compression.clone()),
+        ];
+        self.transaction(|| {
+            for (key, value) in &values {
+                self.conn.execute(
+                    "INSERT OR REPLACE INTO config (key, value) VALUES (?1, ?2)",
+                    params![key, value],
+                )?;
+            }
+            Ok(())
+        })
+    }
+
+    fn table_exists(&self, table: &str) -> Result<bool> {
+        let found: Option<i64> = self.conn.query_row(
+            "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            params![table],
+            |row| row.get(0),
+        ).optional()?;
+        Ok(found.is_some())
+    }
+
+    fn check_schema_version(&self) -> Result<()> {
+        let found: u32 = self.conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if found > SCHEMA_VERSION {
+            return Err(Error::SchemaMismatch { expected: SCHEMA_VERSION, found });
+        }
+
+        Ok(())
+    }
+
     fn create_tables(&self) -> Result<()> {
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS config (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            )",
+            [],
+        )?;
         self.conn.execute(
             "CREATE TABLE IF NOT EXISTS snapshots (
                 id INTEGER PRIMARY KEY AUTOINCREMENT,
@@ -40,6 +315,35 @@ impl Database {
             )",
             [],
         )?;
+        self.add_column_if_missing("snapshots", "message", "TEXT")?;
+        self.add_column_if_missing("snapshots", "protected", "INTEGER NOT NULL DEFAULT 0")?;
+        // Forgotten snapshots are hidden until `gc` removes them or `undelete` brings them back
+        self.add_column_if_missing("snapshots", "deleted_at", "TEXT")?;
+        // Identifies a snapshot across repositories, so copies are not made twice
+        self.add_column_if_missing("snapshots", "uuid", "TEXT")?;
+        self.assign_missing_snapshot_uuids()?;
+        self.conn.execute("CREATE UNIQUE INDEX IF NOT EXISTS snapshots_uuid ON snapshots (uuid)", [])?;
+
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS snapshot_tags (
+                snapshot_id INTEGER NOT NULL,
+                tag TEXT NOT NULL,
+                PRIMARY KEY (snapshot_id, tag),
+                FOREIGN KEY (snapshot_id) REFERENCES snapshots (id)
+            )",
+            [],
+        )?;
+
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS snapshot_sources (
+                snapshot_id INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                path TEXT NOT NULL,
+                PRIMARY KEY (snapshot_id, name),
+                FOREIGN KEY (snapshot_id) REFERENCES snapshots (id)
+            )",
+            [],
+        )?;
 
         self.conn.execute(
             "CREATE TABLE IF NOT EXISTS content_blocks (
@@ -50,6 +354,33 @@ impl Database {
             [],
         )?;
 
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS packs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                size INTEGER NOT NULL,
+                data BLOB NOT NULL
+            )",
+            [],
+        )?;
+
+        // Packed blocks keep an empty `content` and point into a pack instead
+        self.add_column_if_missing("content_blocks", "pack_id", "INTEGER REFERENCES packs (id)")?;
+        self.add_column_if_missing("content_blocks", "pack_offset", "INTEGER")?;
+
+        // Chunked blocks keep an empty `content` and are reassembled from `content_chunks`
+        self.add_column_if_missing("content_blocks", "chunked", "INTEGER NOT NULL DEFAULT 0")?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS content_chunks (
+                content_hash TEXT NOT NULL,
+                seq INTEGER NOT NULL,
+                chunk_hash TEXT NOT NULL,
+                PRIMARY KEY (content_hash, seq),
+                FOREIGN KEY (content_hash) REFERENCES content_blocks (hash),
+                FOREIGN KEY (chunk_hash) REFERENCES content_blocks (hash)
+            )",
+            [],
+        )?;
+
         self.conn.execute(
             "CREATE TABLE IF NOT EXISTS files (
                 id INTEGER PRIMARY KEY AUTOINCREMENT,
@@ -60,6 +391,8 @@ impl Database {
             )",
             [],
         )?;
+        self.add_column_if_missing("files", "mode", "INTEGER")?;
+        self.add_column_if_missing("files", "mtime", "INTEGER")?;
 
         self.conn.execute(
             "CREATE TABLE IF NOT EXISTS snapshot_files (
@@ -72,53 +405,369 @@ impl Database {
             [],
         )?;
 
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS snapshot_errors (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                snapshot_id INTEGER NOT NULL,
+                path TEXT NOT NULL,
+                error TEXT NOT NULL,
+                FOREIGN KEY (snapshot_id) REFERENCES snapshots (id)
+            )",
+            [],
+        )?;
+
+        Ok(())
+    }
+
+    fn add_column_if_missing(&self, table: &str, column: &str, definition: &str) -> Result<()> {
+        let mut stmt = self.conn.prepare(&format!("PRAGMA table_info({})", table))?;
+        let exists = stmt.query_map([], |row| row.get::<_, String>(1))?
+            .filter_map(|name| name.ok())
+            .any(|name| name == column);
+
+        if !exists {
+            self.conn.execute(
+                &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+                [],
+            )?;
+        }
+
         Ok(())
     }
 
     pub fn create_snapshot(&self, target_directory: &str) -> Result<u32> {
-        let timestamp = Utc::now().to_rfc3339();
-        
+        self.insert_snapshot(&Utc::now(), target_directory)
+    }
+
+    pub fn insert_snapshot(&self, timestamp: &DateTime<Utc>, target_directory: &str) -> Result<u32> {
+        self.insert_snapshot_with_uuid(timestamp, target_directory, &new_snapshot_uuid())
+    }
+
+    /// Inserts a snapshot copied from another repository, keeping its UUID.
+    pub fn insert_snapshot_with_uuid(&self, timestamp: &DateTime<Utc>, target_directory: &str, uuid: &str) -> Result<u32> {
+        let timestamp = timestamp.to_rfc3339();
+
         self.conn.execute(
-            "INSERT INTO snapshots (timestamp, target_directory) VALUES (?1, ?2)",
-            params![timestamp, target_directory],
+            "INSERT INTO snapshots (timestamp, target_directory, uuid) VALUES (?1, ?2, ?3)",
+            params![timestamp, target_directory, uuid],
         )?;
 
         let snapshot_id = self.conn.last_insert_rowid() as u32;
         Ok(snapshot_id)
     }
 
+    /// Id of the snapshot with this UUID, including forgotten snapshots.
+    pub fn find_snapshot_by_uuid(&self, uuid: &str) -> Result<Option<u32>> {
+        Ok(self.conn.query_row(
+            "SELECT id FROM snapshots WHERE uuid = ?1",
+            params![uuid],
+            |row| row.get(0),
+        ).optional()?)
+    }
+
+    /// Snapshots taken before UUIDs were recorded get one when the database is opened.
+    fn assign_missing_snapshot_uuids(&self) -> Result<()> {
+        let ids: Vec<u32> = self.conn.prepare("SELECT id FROM snapshots WHERE uuid IS NULL")?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        for id in ids {
+            self.conn.execute("UPDATE snapshots SET uuid = ?1 WHERE id = ?2", params![new_snapshot_uuid(), id])?;
+        }
+        Ok(())
+    }
+
+    pub fn set_snapshot_message(&self, snapshot_id: u32, message: Option<&str>) -> Result<()> {
+        self.conn.execute(
+            "UPDATE snapshots SET message = ?1 WHERE id = ?2",
+            params![message, snapshot_id],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn add_snapshot_source(&self, snapshot_id: u32, source: &SnapshotSource) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO snapshot_sources (snapshot_id, name, path) VALUES (?1, ?2, ?3)",
+            params![snapshot_id, source.name, source.path],
+        )?;
+
+        Ok(())
+    }
+
+    /// Source directories of every snapshot, keyed by snapshot id.
+    fn all_snapshot_sources(&self) -> Result<HashMap<u32, Vec<SnapshotSource>>> {
+        let mut stmt = self.conn.prepare("SELECT snapshot_id, name, path FROM snapshot_sources ORDER BY rowid")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, u32>(0)?, SnapshotSource { name: row.get(1)?, path: row.get(2)? }))
+        })?;
+
+        let mut sources: HashMap<u32, Vec<SnapshotSource>> = HashMap::new();
+        for row in rows {
+            let (snapshot_id, source) = row?;
+            sources.entry(snapshot_id).or_default().push(source);
+        }
+
+        Ok(sources)
+    }
+
+    /// Protected snapshots are refused by prune until they are unprotected.
+    pub fn set_snapshot_protected(&self, snapshot_id: u32, protected: bool) -> Result<()> {
+        let updated = self.conn.execute(
+            "UPDATE snapshots SET protected = ?1 WHERE id = ?2 AND deleted_at IS NULL",
+            params![protected, snapshot_id],
+        )?;
+        if updated == 0 {
+            return Err(Error::SnapshotNotFound(snapshot_id));
+        }
+
+        Ok(())
+    }
+
+    pub fn snapshot_is_protected(&self, snapshot_id: u32) -> Result<bool> {
+        let protected = self.conn.query_row(
+            "SELECT protected FROM snapshots WHERE id = ?1 AND deleted_at IS NULL",
+            params![snapshot_id],
+            |row| row.get(0),
+        ).optional()?
+            .ok_or(Error::SnapshotNotFound(snapshot_id))?;
+
+        Ok(protected)
+    }
+
+    /// Tags a snapshot. Returns false if it already had the tag.
+    pub fn add_snapshot_tag(&self, snapshot_id: u32, tag: &str) -> Result<bool> {
+        if tag.is_empty() || tag.contains(|c: char| c.is_whitespace() || c == ',') {
+            return Err(Error::InvalidInput(format!("invalid tag {:?}: tags must be non-empty without spaces or commas", tag)));
+        }
+        if !self.snapshot_exists(snapshot_id)? {
+            return Err(Error::SnapshotNotFound(snapshot_id));
+        }
+
+        let inserted = self.conn.execute(
+            "INSERT OR IGNORE INTO snapshot_tags (snapshot_id, tag) VALUES (?1, ?2)",
+            params![snapshot_id, tag],
+        )?;
+
+        Ok(inserted > 0)
+    }
+
+    /// Removes a tag from a snapshot. Returns false if the snapshot did not have the tag.
+    pub fn remove_snapshot_tag(&self, snapshot_id: u32, tag: &str) -> Result<bool> {
+        if !self.snapshot_exists(snapshot_id)? {
+            return Err(Error::SnapshotNotFound(snapshot_id));
+        }
+
+        let removed = self.conn.execute(
+            "DELETE FROM snapshot_tags WHERE snapshot_id = ?1 AND tag = ?2",
+            params![snapshot_id, tag],
+        )?;
+
+        Ok(removed > 0)
+    }
+
+    pub fn get_snapshot_tags(&self, snapshot_id: u32) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT tag FROM snapshot_tags WHERE snapshot_id = ?1 ORDER BY tag"
+        )?;
+        let rows = stmt.query_map(params![snapshot_id], |row| row.get(0))?;
+
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Tags of every snapshot, keyed by snapshot id.
+    fn all_snapshot_tags(&self) -> Result<HashMap<u32, Vec<String>>> {
+        let mut stmt = self.conn.prepare("SELECT snapshot_id, tag FROM snapshot_tags ORDER BY tag")?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, u32>(0)?, row.get::<_, String>(1)?)))?;
+
+        let mut tags: HashMap<u32, Vec<String>> = HashMap::new();
+        for row in rows {
+            let (snapshot_id, tag) = row?;
+            tags.entry(snapshot_id).or_default().push(tag);
+        }
+
+        Ok(tags)
+    }
+
+    /// Small blobs are held in a pending pack until `flush_packs`. `transaction` flushes on
+    /// success and discards them on failure; anything left over is written on drop.
     pub fn store_content(&self, hash: &str, content: &[u8]) -> Result<bool> {
         // Only insert if content doesn't already exist
-        let exists: bool = self.conn.query_row(
-            "SELECT 1 FROM content_blocks WHERE hash = ?1",
-            params![hash],
-            |_| Ok(true),
-        ).unwrap_or(false);
+        let exists = self.content_exists(hash)?;
 
         if !exists {
+            if content.len() < PACK_BLOB_THRESHOLD {
+                let full = {
+                    let mut pending = self.pending_pack.borrow_mut();
+                    pending.add(hash, content);
+                    pending.is_full()
+                };
+                if full {
+                    self.flush_packs()?;
+                }
+            } else {
+                self.conn.execute(
+                    "INSERT INTO content_blocks (hash, size, content) VALUES (?1, ?2, ?3)",
+                    params![hash, content.len() as i64, content],
+                )?;
+            }
+        }
+
+        Ok(exists)
+    }
+
+    /// Records content that was stored as a sequence of chunks, each already added with
+    /// `store_content`. Returns whether the content existed.
+    pub fn store_chunked_content(&self, hash: &str, size: u64, chunk_hashes: &[String]) -> Result<bool> {
+        if self.content_exists(hash)? {
+            return Ok(true);
+        }
+
+        self.conn.execute(
+            "INSERT INTO content_blocks (hash, size, content, chunked) VALUES (?1, ?2, X'', 1)",
+            params![hash, size as i64],
+        )?;
+        for (seq, chunk_hash) in chunk_hashes.iter().enumerate() {
             self.conn.execute(
-                "INSERT INTO content_blocks (hash, size, content) VALUES (?1, ?2, ?3)",
-                params![hash, content.len() as i64, content],
+                "INSERT INTO content_chunks (content_hash, seq, chunk_hash) VALUES (?1, ?2, ?3)",
+                params![hash, seq as i64, chunk_hash],
             )?;
         }
 
-        Ok(exists)
+        Ok(false)
+    }
+
+    /// Writes the pack currently being built, if any, and indexes its blobs in `content_blocks`.
+    pub fn flush_packs(&self) -> Result<()> {
+        let (data, entries) = {
+            let mut pending = self.pending_pack.borrow_mut();
+            if pending.is_empty() {
+                return Ok(());
+            }
+            pending.take()
+        };
+
+        self.with_savepoint(|| {
+            self.conn.execute(
+                "INSERT INTO packs (size, data) VALUES (?1, ?2)",
+                params![data.len() as i64, data],
+            )?;
+            let pack_id = self.conn.last_insert_rowid();
+
+            for entry in &entries {
+                self.conn.execute(
+                    "INSERT OR REPLACE INTO content_blocks (hash, size, content, pack_id, pack_offset)
+                     VALUES (?1, ?2, X'', ?3, ?4)",
+                    params![entry.hash, entry.length as i64, pack_id, entry.offset as i64],
+                )?;
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Runs `f` in a transaction, writing any pending pack before committing. Foreign key checks
+    /// are deferred to commit so files may reference blobs that are still in the pending pack.
+    pub fn transaction<T>(&self, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        let result = self.with_savepoint(|| {
+            self.conn.execute_batch("PRAGMA defer_foreign_keys = ON")?;
+            let value = f()?;
+            self.flush_packs()?;
+            Ok(value)
+        });
+        if result.is_err() && self.conn.is_autocommit() {
+            // Nothing that referenced the pending blobs was kept
+            self.pending_pack.borrow_mut().take();
+        }
+        result
+    }
+
+    /// Runs `f` atomically. Savepoints nest, so this also works inside an outer transaction.
+    fn with_savepoint<T>(&self, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        self.conn.execute_batch("SAVEPOINT backuptool")?;
+        match f() {
+            Ok(value) => {
+                self.conn.execute_batch("RELEASE backuptool")?;
+                Ok(value)
+            }
+            Err(e) => {
+                self.conn.execute_batch("ROLLBACK TO backuptool; RELEASE backuptool")?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Rewrites packs that are mostly garbage and drops packs with no live blobs.
+    /// Returns the number of packs removed.
+    pub fn repack(&self) -> Result<usize> {
+        self.flush_packs()?;
+
+        let candidates: Vec<(i64, i64, i64)> = {
+            let mut stmt = self.conn.prepare(
+                "SELECT p.id, p.size, COALESCE(SUM(cb.size), 0)
+                 FROM packs p
+                 LEFT JOIN content_blocks cb ON cb.pack_id = p.id
+                 GROUP BY p.id, p.size"
+            )?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+            rows.collect::<rusqlite::Result<_>>()?
+        };
+
+        self.transaction(|| {
+            let mut removed = 0;
+
+            for (pack_id, pack_size, live_size) in candidates {
+                if live_size as f64 >= pack_size as f64 * REPACK_LIVE_RATIO {
+                    continue;
+                }
+
+                let live_hashes: Vec<String> = {
+                    let mut stmt = self.conn.prepare("SELECT hash FROM content_blocks WHERE pack_id = ?1")?;
+                    let rows = stmt.query_map(params![pack_id], |row| row.get(0))?;
+                    rows.collect::<rusqlite::Result<_>>()?
+                };
+                for hash in live_hashes {
+                    let content = self.get_file_content(&hash)?;
+                    self.pending_pack.borrow_mut().add(&hash, &content);
+                    if self.pending_pack.borrow().is_full() {
+                        self.flush_packs()?;
+                    }
+                }
+
+                self.conn.execute("DELETE FROM packs WHERE id = ?1", params![pack_id])?;
+                removed += 1;
+            }
+
+            // Surviving blobs are re-indexed into fresh packs before the transaction commits
+            Ok(removed)
+        })
     }
 
     pub fn add_file_to_snapshot(&self, snapshot_id: u32, path: &str, content_hash: &str, size: u64) -> Result<()> {
+        self.add_file_with_metadata(snapshot_id, path, content_hash, size, None, None)
+    }
+
+    pub fn add_file_with_metadata(
+        &self,
+        snapshot_id: u32,
+        path: &str,
+        content_hash: &str,
+        size: u64,
+        mode: Option<u32>,
+        mtime: Option<i64>,
+    ) -> Result<()> {
         // Check if file already exists
         let file_id: Option<i64> = self.conn.query_row(
-            "SELECT id FROM files WHERE path = ?1 AND content_hash = ?2",
-            params![path, content_hash],
-            |row| Ok(row.get(0)?),
+            "SELECT id FROM files WHERE path = ?1 AND content_hash = ?2 AND mode IS ?3 AND mtime IS ?4",
+            params![path, content_hash, mode, mtime],
+            |row| row.get(0),
         ).ok();
 
         let file_id = match file_id {
             Some(id) => id,
             None => {
                 self.conn.execute(
-                    "INSERT INTO files (path, content_hash, size) VALUES (?1, ?2, ?3)",
-                    params![path, content_hash, size as i64],
+                    "INSERT INTO files (path, content_hash, size, mode, mtime) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![path, content_hash, size as i64, mode, mtime],
                 )?;
                 self.conn.last_insert_rowid()
             }
@@ -133,11 +782,12 @@ impl Database {
         Ok(())
     }
 
-    pub fn list_snapshots(&self) -> Result<()> {
+    pub fn list_snapshots(&self) -> Result<Vec<SnapshotInfo>> {
         let mut stmt = self.conn.prepare(
-            "SELECT s.id, s.timestamp,
+            "SELECT s.id, s.uuid, s.timestamp, s.message, s.protected,
                     COALESCE(SUM(f.size), 0) as total_size,
-                    COALESCE(SUM(CASE WHEN cnt.usage_count = 1 THEN f.size ELSE 0 END), 0) as distinct_size
+                    COALESCE(SUM(CASE WHEN cnt.usage_count = 1 THEN f.size ELSE 0 END), 0) as distinct_size,
+                    (SELECT COUNT(*) FROM snapshot_errors e WHERE e.snapshot_id = s.id) as error_count
              FROM snapshots s
              LEFT JOIN snapshot_files sf ON s.id = sf.snapshot_id
              LEFT JOIN files f ON sf.file_id = f.id
@@ -145,42 +795,44 @@ impl Database {
                  SELECT content_hash, COUNT(*) as usage_count
                  FROM files f2
                  JOIN snapshot_files sf2 ON f2.id = sf2.file_id
+                 JOIN snapshots s2 ON s2.id = sf2.snapshot_id
+                 WHERE s2.deleted_at IS NULL
                  GROUP BY content_hash
              ) cnt ON f.content_hash = cnt.content_hash
-             GROUP BY s.id, s.timestamp
+             WHERE s.deleted_at IS NULL
+             GROUP BY s.id, s.uuid, s.timestamp, s.message, s.protected
              ORDER BY s.id"
         )?;
 
         let snapshot_iter = stmt.query_map([], |row| {
             Ok(SnapshotInfo {
                 id: row.get(0)?,
-                timestamp: DateTime::parse_from_rfc3339(&row.get::<_, String>(1)?)
+                uuid: row.get(1)?,
+                timestamp: DateTime::parse_from_rfc3339(&row.get::<_, String>(2)?)
                     .unwrap().with_timezone(&Utc),
-                total_size: row.get::<_, i64>(2)? as u64,
-                distinct_size: row.get::<_, i64>(3)? as u64,
+                message: row.get(3)?,
+                protected: row.get(4)?,
+                total_size: row.get::<_, i64>(5)? as u64,
+                distinct_size: row.get::<_, i64>(6)? as u64,
+                error_count: row.get::<_, i64>(7)? as u64,
+                tags: Vec::new(),
             })
         })?;
 
-        println!("SNAPSHOT  TIMESTAMP            SIZE  DISTINCT_SIZE");
-        let mut total_db_size = 0u64;
-        
+        let mut tags = self.all_snapshot_tags()?;
+        let mut snapshots = Vec::new();
         for snapshot in snapshot_iter {
-            let snapshot = snapshot?;
-            total_db_size += snapshot.distinct_size;
-            println!("{:<8}  {:<19}  {:<4}  {}", 
-                     snapshot.id, 
-                     snapshot.timestamp.format("%Y-%m-%d %H:%M:%S"),
-                     snapshot.total_size,
-                     snapshot.distinct_size);
-        }
-        
-        println!("total                          {}", total_db_size);
-        Ok(())
+            let mut snapshot = snapshot?;
+            snapshot.tags = tags.remove(&snapshot.id).unwrap_or_default();
+            snapshots.push(snapshot);
+        }
+
+        Ok(snapshots)
     }
 
     pub fn get_snapshot_files(&self, snapshot_id: u32) -> Result<Vec<FileInfo>> {
         let mut stmt = self.conn.prepare(
-            "SELECT f.path, f.content_hash
+            "SELECT f.path, f.content_hash, f.size, f.mode, f.mtime
              FROM files f
              JOIN snapshot_files sf ON f.id = sf.file_id
              WHERE sf.snapshot_id = ?1"
@@ -190,6 +842,9 @@ impl Database {
             Ok(FileInfo {
                 path: row.get(0)?,
                 content_hash: row.get(1)?,
+                size: row.get::<_, i64>(2)? as u64,
+                mode: row.get(3)?,
+                mtime: row.get(4)?,
             })
         })?;
 
@@ -201,17 +856,246 @@ impl Database {
         Ok(files)
     }
 
-    pub fn get_file_content(&self, content_hash: &str) -> Result<Vec<u8>> {
-        let content: Vec<u8> = self.conn.query_row(
-            "SELECT content FROM content_blocks WHERE hash = ?1",
+    pub fn get_snapshot(&self, snapshot_id: u32) -> Result<SnapshotRecord> {
+        self.get_snapshots()?
+            .into_iter()
+            .find(|s| s.id == snapshot_id)
+            .ok_or(Error::SnapshotNotFound(snapshot_id))
+    }
+
+    pub fn get_snapshots(&self) -> Result<Vec<SnapshotRecord>> {
+        self.snapshot_records(false)
+    }
+
+    /// Live snapshots, or with `forgotten` the ones that are forgotten but not yet collected.
+    fn snapshot_records(&self, forgotten: bool) -> Result<Vec<SnapshotRecord>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT id, timestamp, target_directory, message, protected, uuid FROM snapshots
+             WHERE deleted_at IS {} ORDER BY id",
+            if forgotten { "NOT NULL" } else { "NULL" }
+        ))?;
+
+        let snapshot_iter = stmt.query_map([], |row| {
+            Ok(SnapshotRecord {
+                id: row.get(0)?,
+                timestamp: DateTime::parse_from_rfc3339(&row.get::<_, String>(1)?)
+                    .unwrap().with_timezone(&Utc),
+                target_directory: row.get(2)?,
+                message: row.get(3)?,
+                protected: row.get(4)?,
+                uuid: row.get(5)?,
+                tags: Vec::new(),
+                sources: Vec::new(),
+            })
+        })?;
+
+        let mut tags = self.all_snapshot_tags()?;
+        let mut sources = self.all_snapshot_sources()?;
+        let mut snapshots = Vec::new();
+        for snapshot in snapshot_iter {
+            let mut snapshot = snapshot?;
+            snapshot.tags = tags.remove(&snapshot.id).unwrap_or_default();
+            // Snapshots taken before sources were recorded had exactly one, unprefixed root
+            snapshot.sources = sources.remove(&snapshot.id)
+                .unwrap_or_else(|| vec![SnapshotSource::from_path(&snapshot.target_directory)]);
+            snapshots.push(snapshot);
+        }
+
+        Ok(snapshots)
+    }
+
+    /// Resolves a selector to the id of the snapshot it refers to.
+    pub fn resolve_snapshot(&self, selector: &SnapshotSelector) -> Result<u32> {
+        self.resolve(selector, false)
+    }
+
+    /// Resolves a selector among forgotten snapshots, for `undelete`. Numbers are returned
+    /// as they are so that `undelete_snapshot` can explain why a live one cannot be undeleted.
+    pub fn resolve_forgotten_snapshot(&self, selector: &SnapshotSelector) -> Result<u32> {
+        self.resolve(selector, true)
+    }
+
+    fn resolve(&self, selector: &SnapshotSelector, forgotten: bool) -> Result<u32> {
+        let (filter, skip, before) = match selector {
+            SnapshotSelector::Id(id) => {
+                if !forgotten && !self.snapshot_exists(*id)? {
+                    return Err(Error::SnapshotNotFound(*id));
+                }
+                return Ok(*id);
+            }
+            SnapshotSelector::Uuid(prefix) => return self.resolve_uuid_prefix(prefix, forgotten),
+            SnapshotSelector::Latest { filter, skip } => (filter, *skip, None),
+            SnapshotSelector::AtOrBefore { filter, time } => (filter, 0, Some(*time)),
+        };
+
+        let mut candidates: Vec<SnapshotRecord> = self.snapshot_records(forgotten)?
+            .into_iter()
+            .filter(|s| filter.matches(s))
+            .filter(|s| before.is_none_or(|time| s.timestamp <= time))
+            .collect();
+        candidates.sort_by_key(|s| std::cmp::Reverse((s.timestamp, s.id)));
+
+        candidates.get(skip)
+            .map(|s| s.id)
+            .ok_or_else(|| Error::NoMatchingSnapshot(selector.to_string()))
+    }
+
+    fn resolve_uuid_prefix(&self, prefix: &str, forgotten: bool) -> Result<u32> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT id FROM snapshots WHERE deleted_at IS {} AND replace(uuid, '-', '') LIKE ?1 || '%' ORDER BY id",
+            if forgotten { "NOT NULL" } else { "NULL" }
+        ))?;
+        let ids: Vec<u32> = stmt.query_map(params![prefix], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+
+        match ids.as_slice() {
+            [id] => Ok(*id),
+            [] => Err(Error::NoMatchingSnapshot(format!("uuid:{}", prefix))),
+            _ => Err(Error::InvalidInput(format!(
+                "snapshot ID prefix {} is ambiguous, it matches snapshots {}",
+                prefix, ids.iter().map(u32::to_string).collect::<Vec<_>>().join(", ")
+            ))),
+        }
+    }
+
+    pub fn get_content_hashes(&self) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare("SELECT hash FROM content_blocks ORDER BY hash")?;
+        let hash_iter = stmt.query_map([], |row| row.get(0))?;
+
+        let mut hashes = Vec::new();
+        for hash in hash_iter {
+            hashes.push(hash?);
+        }
+
+        Ok(hashes)
+    }
+
+    pub fn content_exists(&self, content_hash: &str) -> Result<bool> {
+        if self.pending_pack.borrow().contains(content_hash) {
+            return Ok(true);
+        }
+
+        let exists: bool = self.conn.query_row(
+            "SELECT 1 FROM content_blocks WHERE hash = ?1",
             params![content_hash],
-            |row| Ok(row.get(0)?),
+            |_| Ok(true),
+        ).unwrap_or(false);
+
+        Ok(exists)
+    }
+
+    pub fn get_file_content(&self, content_hash: &str) -> Result<Vec<u8>> {
+        let mut content = Vec::new();
+        self.read_file_content(content_hash, |piece| {
+            content.extend_from_slice(piece);
+            Ok(())
+        })?;
+
+        Ok(content)
+    }
+
+    /// Passes the content to `f` one piece at a time, so chunked content is never held in
+    /// memory as a whole.
+    pub fn read_file_content(&self, content_hash: &str, mut f: impl FnMut(&[u8]) -> Result<()>) -> Result<()> {
+        let chunk_hashes = self.get_content_chunks(content_hash)?;
+
+        if chunk_hashes.is_empty() {
+            return f(&self.get_block(content_hash)?);
+        }
+        for chunk_hash in chunk_hashes {
+            f(&self.get_block(&chunk_hash)?)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reader over the content, fetching one block at a time.
+    pub fn content_reader(&self, content_hash: &str) -> Result<ContentReader<'_>> {
+        let mut blocks = self.get_content_chunks(content_hash)?;
+        if blocks.is_empty() {
+            blocks.push(content_hash.to_string());
+        }
+
+        Ok(ContentReader { db: self, blocks: blocks.into_iter(), current: Vec::new(), position: 0 })
+    }
+
+    /// Hashes of the chunks making up chunked content, or nothing for ordinary blocks.
+    pub fn get_content_chunks(&self, content_hash: &str) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT chunk_hash FROM content_chunks WHERE content_hash = ?1 ORDER BY seq"
         )?;
+        let rows = stmt.query_map(params![content_hash], |row| row.get(0))?;
+
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Content of a single unchunked block, from the pending pack, a pack or the row itself.
+    fn get_block(&self, content_hash: &str) -> Result<Vec<u8>> {
+        if let Some(content) = self.pending_pack.borrow().get(content_hash) {
+            return Ok(content.to_vec());
+        }
+
+        let (content, size, pack_id, pack_offset, chunked): (Vec<u8>, i64, Option<i64>, Option<i64>, bool) = self.conn.query_row(
+            "SELECT content, size, pack_id, pack_offset, chunked FROM content_blocks WHERE hash = ?1",
+            params![content_hash],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)),
+        ).optional()?
+            .ok_or_else(|| Error::ContentNotFound(content_hash.to_string()))?;
+        if chunked {
+            return Err(Error::Corruption(format!("chunk list of {} is missing", content_hash)));
+        }
+
+        let (pack_id, pack_offset) = match (pack_id, pack_offset) {
+            (Some(pack_id), Some(pack_offset)) => (pack_id, pack_offset),
+            _ => return Ok(content),
+        };
+
+        // Read just this blob's range out of the pack
+        let blob = self.conn.blob_open(DatabaseName::Main, "packs", "data", pack_id, true)
+            .optional()?
+            .ok_or_else(|| Error::Corruption(format!("pack {} referenced by {} is missing", pack_id, content_hash)))?;
+        let mut content = vec![0u8; size as usize];
+        blob.read_at_exact(&mut content, pack_offset as usize)
+            .map_err(|_| Error::Corruption(format!("pack {} is truncated", pack_id)))?;
 
         Ok(content)
     }
 
+    pub fn record_snapshot_error(&self, snapshot_id: u32, path: &str, error: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO snapshot_errors (snapshot_id, path, error) VALUES (?1, ?2, ?3)",
+            params![snapshot_id, path, error],
+        )?;
+
+        Ok(())
+    }
+
+    /// Paths skipped while taking a snapshot, with the error that caused each skip.
+    pub fn get_snapshot_errors(&self, snapshot_id: u32) -> Result<Vec<(String, String)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT path, error FROM snapshot_errors WHERE snapshot_id = ?1 ORDER BY id"
+        )?;
+        let rows = stmt.query_map(params![snapshot_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
     pub fn delete_snapshot(&self, snapshot_id: u32) -> Result<()> {
+        self.conn.execute(
+            "DELETE FROM snapshot_errors WHERE snapshot_id = ?1",
+            params![snapshot_id],
+        )?;
+
+        self.conn.execute(
+            "DELETE FROM snapshot_tags WHERE snapshot_id = ?1",
+            params![snapshot_id],
+        )?;
+
+        self.conn.execute(
+            "DELETE FROM snapshot_sources WHERE snapshot_id = ?1",
+            params![snapshot_id],
+        )?;
+
         // Delete snapshot-file relationships
         self.conn.execute(
             "DELETE FROM snapshot_files WHERE snapshot_id = ?1",
@@ -227,33 +1111,319 @@ impl Database {
         Ok(())
     }
 
-    pub fn cleanup_orphaned_content(&self) -> Result<()> {
+    /// Hides a snapshot from every command until it is undeleted or garbage collected.
+    pub fn forget_snapshot(&self, snapshot_id: u32) -> Result<()> {
+        let updated = self.conn.execute(
+            "UPDATE snapshots SET deleted_at = ?1 WHERE id = ?2 AND deleted_at IS NULL",
+            params![Utc::now().to_rfc3339(), snapshot_id],
+        )?;
+        if updated == 0 {
+            return Err(Error::SnapshotNotFound(snapshot_id));
+        }
+
+        Ok(())
+    }
+
+    /// Brings back a forgotten snapshot that has not been garbage collected yet.
+    pub fn undelete_snapshot(&self, snapshot_id: u32) -> Result<()> {
+        if self.snapshot_exists(snapshot_id)? {
+            return Err(Error::InvalidInput(format!("snapshot {} has not been forgotten", snapshot_id)));
+        }
+
+        let updated = self.conn.execute(
+            "UPDATE snapshots SET deleted_at = NULL WHERE id = ?1 AND deleted_at IS NOT NULL",
+            params![snapshot_id],
+        )?;
+        if updated == 0 {
+            return Err(Error::SnapshotNotFound(snapshot_id));
+        }
+
+        Ok(())
+    }
+
+    /// Snapshots that have been forgotten but not yet garbage collected, oldest deletion first.
+    pub fn list_forgotten_snapshots(&self) -> Result<Vec<ForgottenSnapshot>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, timestamp, target_directory, deleted_at FROM snapshots
+             WHERE deleted_at IS NOT NULL ORDER BY id"
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(ForgottenSnapshot {
+                id: row.get(0)?,
+                timestamp: DateTime::parse_from_rfc3339(&row.get::<_, String>(1)?)
+                    .unwrap().with_timezone(&Utc),
+                target_directory: row.get(2)?,
+                deleted_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(3)?)
+                    .unwrap().with_timezone(&Utc),
+            })
+        })?;
+
+        let mut snapshots = rows.collect::<rusqlite::Result<Vec<_>>>()?;
+        snapshots.sort_by_key(|s| (s.deleted_at, s.id));
+        Ok(snapshots)
+    }
+
+    /// Deletes files, chunk lists and content blocks no snapshot refers to any more,
+    /// including snapshots that are only forgotten.
+    pub fn cleanup_orphaned_content(&self) -> Result<CleanupStats> {
+        // Chunked blocks have no content of their own, their chunks are counted separately
+        let orphaned_blocks = "FROM content_blocks WHERE hash NOT IN (
+                SELECT DISTINCT content_hash FROM files
+            ) AND hash NOT IN (
+                SELECT DISTINCT chunk_hash FROM content_chunks
+            )";
+
         // Delete files that are no longer referenced by any snapshot
-        self.conn.execute(
+        let files_removed = self.conn.execute(
             "DELETE FROM files WHERE id NOT IN (
                 SELECT DISTINCT file_id FROM snapshot_files
             )",
             [],
         )?;
 
-        // Delete content blocks that are no longer referenced by any file
+        // Delete chunk lists of content that is no longer referenced by any file
         self.conn.execute(
-            "DELETE FROM content_blocks WHERE hash NOT IN (
+            "DELETE FROM content_chunks WHERE content_hash NOT IN (
                 SELECT DISTINCT content_hash FROM files
             )",
             [],
         )?;
 
+        // Delete content blocks that are no longer referenced by any file or chunk list
+        let bytes_reclaimed: i64 = self.conn.query_row(
+            &format!("SELECT COALESCE(SUM(size), 0) {} AND chunked = 0", orphaned_blocks),
+            [],
+            |row| row.get(0),
+        )?;
+        let blocks_removed = self.conn.execute(&format!("DELETE {}", orphaned_blocks), [])?;
+
+        Ok(CleanupStats {
+            files_removed: files_removed as u64,
+            blocks_removed: blocks_removed as u64,
+            bytes_reclaimed: bytes_reclaimed as u64,
+        })
+    }
+
+    /// Totals over all stored content, whether or not a snapshot still refers to it.
+    pub fn storage_totals(&self) -> Result<StorageTotals> {
+        let (blocks, unique_bytes, block_bytes): (i64, i64, i64) = self.conn.query_row(
+            "SELECT COUNT(*),
+                    COALESCE(SUM(CASE WHEN chunked = 0 THEN size ELSE 0 END), 0),
+                    COALESCE(SUM(length(content)), 0)
+             FROM content_blocks",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+        let pack_bytes: i64 = self.conn.query_row(
+            "SELECT COALESCE(SUM(length(data)), 0) FROM packs",
+            [],
+            |row| row.get(0),
+        )?;
+
+        Ok(StorageTotals {
+            blocks: blocks as u64,
+            unique_bytes: unique_bytes as u64,
+            stored_bytes: (block_bytes + pack_bytes) as u64,
+        })
+    }
+
+    /// Bytes of distinct content that snapshots which are not forgotten refer to, directly or
+    /// through chunks. Unlike `storage_totals` this leaves out forgotten and orphaned content.
+    pub fn live_unique_bytes(&self) -> Result<u64> {
+        let unique_bytes: i64 = self.conn.query_row(
+            "WITH live AS (
+                SELECT DISTINCT f.content_hash AS hash
+                FROM files f
+                JOIN snapshot_files sf ON sf.file_id = f.id
+                JOIN snapshots s ON s.id = sf.snapshot_id
+                WHERE s.deleted_at IS NULL
+             )
+             SELECT COALESCE(SUM(size), 0) FROM content_blocks
+             WHERE chunked = 0 AND (
+                hash IN (SELECT hash FROM live)
+                OR hash IN (SELECT chunk_hash FROM content_chunks WHERE content_hash IN (SELECT hash FROM live))
+             )",
+            [],
+            |row| row.get(0),
+        )?;
+
+        Ok(unique_bytes as u64)
+    }
+
+    /// Problems found without reading any content: damaged pages, dangling references and
+    /// recorded sizes that do not add up.
+    pub fn structural_problems(&self) -> Result<Vec<String>> {
+        let mut problems = Vec::new();
+
+        let mut stmt = self.conn.prepare("PRAGMA integrity_check")?;
+        for message in stmt.query_map([], |row| row.get::<_, String>(0))? {
+            let message = message?;
+            if message != "ok" {
+                problems.push(format!("database: {}", message));
+            }
+        }
+
+        let mut stmt = self.conn.prepare("PRAGMA foreign_key_check")?;
+        let dangling = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, Option<i64>>(1)?, row.get::<_, String>(2)?))
+        })?;
+        for reference in dangling {
+            let (table, rowid, parent) = reference?;
+            let rowid = rowid.map_or_else(|| "?".to_string(), |id| id.to_string());
+            problems.push(format!("{} row {} refers to a missing {} row", table, rowid, parent));
+        }
+
+        // Queries for hashes or paths that break an invariant, with `{}` marking them in the message
+        let invariants = [
+            (
+                "SELECT hash FROM content_blocks WHERE chunked = 0 AND pack_id IS NULL AND length(content) != size",
+                "content block {} does not have its recorded size",
+            ),
+            (
+                "SELECT b.hash FROM content_blocks b JOIN packs p ON p.id = b.pack_id
+                 WHERE b.pack_offset + b.size > length(p.data)",
+                "content block {} extends past the end of its pack",
+            ),
+            (
+                "SELECT b.hash FROM content_blocks b WHERE b.chunked = 1 AND b.size != (
+                     SELECT COALESCE(SUM(c.size), 0) FROM content_chunks cc
+                     JOIN content_blocks c ON c.hash = cc.chunk_hash
+                     WHERE cc.content_hash = b.hash
+                 )",
+                "chunks of content {} do not add up to its size",
+            ),
+            (
+                "SELECT f.path FROM files f JOIN content_blocks b ON b.hash = f.content_hash WHERE f.size != b.size",
+                "file {} does not have the size of its content",
+            ),
+        ];
+        for (sql, message) in invariants {
+            let mut stmt = self.conn.prepare(sql)?;
+            for found in stmt.query_map([], |row| row.get::<_, String>(0))? {
+                problems.push(message.replace("{}", &found?));
+            }
+        }
+
+        Ok(problems)
+    }
+
+    /// Size of the database file in bytes.
+    pub fn file_size(&self) -> Result<u64> {
+        let page_count: u64 = self.conn.query_row("PRAGMA page_count", [], |row| row.get(0))?;
+        let page_size: u64 = self.conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+        Ok(page_count * page_size)
+    }
+
+    /// Gives pages freed by deletions back to the file system. Databases created before
+    /// incremental auto-vacuum was enabled only shrink after `compact`.
+    pub fn reclaim_space(&self) -> Result<()> {
+        // The pragma frees one page per step, so it has to be run to completion
+        let mut stmt = self.conn.prepare("PRAGMA incremental_vacuum")?;
+        let mut rows = stmt.query([])?;
+        while rows.next()?.is_some() {}
         Ok(())
     }
 
+    /// Rewrites the database into a fresh file next to it and swaps it into place, dropping
+    /// all free pages. This also enables incremental auto-vacuum on older databases.
+    pub fn compact(&mut self) -> Result<SizeChange> {
+        self.flush_packs()?;
+        let size_before = self.file_size()?;
+        let path = match self.conn.path() {
+            Some(path) if !path.is_empty() => PathBuf::from(path),
+            _ => return Err(Error::InvalidInput("in-memory databases cannot be compacted".to_string())),
+        };
+
+        let mut temp = path.clone().into_os_string();
+        temp.push(".compact");
+        let temp = PathBuf::from(temp);
+        if temp.exists() {
+            fs::remove_file(&temp).map_err(|e| Error::io(&temp, e))?;
+        }
+
+        self.conn.pragma_update(None, "auto_vacuum", "INCREMENTAL")?;
+        let vacuumed = self.conn.execute("VACUUM INTO ?1", params![temp.to_string_lossy()])
+            .map_err(Error::from)
+            // The copy must be on disk before it replaces the live repository
+            .and_then(|_| fs::File::open(&temp).and_then(|file| file.sync_all()).map_err(|e| Error::io(&temp, e)))
+            .and_then(|_| fs::rename(&temp, &path).map_err(|e| Error::io(&path, e)));
+        if let Err(e) = vacuumed {
+            let _ = fs::remove_file(&temp);
+            return Err(e);
+        }
+        self.conn = open_connection(&path)?;
+        sync_parent_directory(&path)?;
+
+        Ok(SizeChange { size_before, size_after: self.file_size()? })
+    }
+
     pub fn snapshot_exists(&self, snapshot_id: u32) -> Result<bool> {
         let exists: bool = self.conn.query_row(
-            "SELECT 1 FROM snapshots WHERE id = ?1",
+            "SELECT 1 FROM snapshots WHERE id = ?1 AND deleted_at IS NULL",
             params![snapshot_id],
             |_| Ok(true),
         ).unwrap_or(false);
 
         Ok(exists)
     }
-}
\ No newline at end of file
+}
+
+/// Opens a connection with foreign key checks on, which SQLite builds may leave off by default.
+fn open_connection(path: &Path) -> Result<Connection> {
+    let conn = Connection::open(path)?;
+    conn.pragma_update(None, "foreign_keys", true)?;
+    Ok(conn)
+}
+
+/// Makes a rename into `path`'s directory durable.
+#[cfg(unix)]
+fn sync_parent_directory(path: &Path) -> Result<()> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    fs::File::open(parent).and_then(|dir| dir.sync_all()).map_err(|e| Error::io(parent, e))
+}
+
+/// Directories cannot be opened for syncing on other platforms.
+#[cfg(not(unix))]
+fn sync_parent_directory(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+/// Streams stored content; see `Database::content_reader`. Repository errors are returned
+/// as `io::Error`s wrapping the original `Error`.
+pub struct ContentReader<'a> {
+    db: &'a Database,
+    blocks: std::vec::IntoIter<String>,
+    current: Vec<u8>,
+    position: usize,
+}
+
+impl Read for ContentReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.current.len() {
+            match self.blocks.next() {
+                Some(hash) => {
+                    self.current = self.db.get_block(&hash).map_err(io::Error::other)?;
+                    self.position = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+
+        let read = buf.len().min(self.current.len() - self.position);
+        buf[..read].copy_from_slice(&self.current[self.position..self.position + read]);
+        self.position += read;
+        Ok(read)
+    }
+}
+
+impl Drop for Database {
+    fn drop(&mut self) {
+        // Content stored outside a transaction must not be lost; Drop cannot return the error
+        if let Err(e) = self.flush_packs() {
+            eprintln!("Warning: could not write pending content: {}", e);
+        }
+    }
+}
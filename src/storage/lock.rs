@@ -0,0 +1,216 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::error::{Error, Result};
+
+/// How often a held lock's file is touched to show its holder is still running.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+/// A lock whose heartbeat is older than this is considered abandoned.
+const STALE_AFTER: Duration = Duration::from_secs(300);
+/// Delay between attempts while waiting for a conflicting lock to be released.
+const RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Shared locks may be held together; an exclusive lock excludes every other lock.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LockKind {
+    Shared,
+    Exclusive,
+}
+
+impl fmt::Display for LockKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LockKind::Shared => "shared",
+            LockKind::Exclusive => "exclusive",
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct LockFile {
+    kind: LockKind,
+    pid: u32,
+    host: String,
+    acquired_at: DateTime<Utc>,
+}
+
+/// A lock found in the repository's lock directory.
+#[derive(Debug, Clone, Serialize)]
+pub struct LockInfo {
+    pub kind: LockKind,
+    pub pid: u32,
+    pub host: String,
+    pub acquired_at: DateTime<Utc>,
+    pub heartbeat: DateTime<Utc>,
+    pub stale: bool,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl fmt::Display for LockInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f, "{} lock held by pid {} on {} since {}",
+            self.kind, self.pid, self.host, self.acquired_at.format("%Y-%m-%d %H:%M:%S")
+        )
+    }
+}
+
+/// A repository lock held until dropped. Locks live as one file per holder in
+/// `<database>.locks/`, and a background thread refreshes the file's mtime as a heartbeat.
+pub struct RepositoryLock {
+    path: PathBuf,
+    stop: Option<mpsc::Sender<()>>,
+    heartbeat: Option<thread::JoinHandle<()>>,
+}
+
+impl RepositoryLock {
+    /// Takes a lock on the repository at `db_path`, waiting up to `wait` for conflicting
+    /// locks to be released. Stale locks left by crashed processes are removed on the way.
+    pub fn acquire(db_path: &Path, kind: LockKind, wait: Duration) -> Result<Self> {
+        let dir = lock_dir(db_path);
+        fs::create_dir_all(&dir).map_err(|e| Error::io(&dir, e))?;
+
+        let pid = std::process::id();
+        let host = hostname();
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+        let path = dir.join(format!("{}-{}-{}.lock", host, pid, nanos));
+        let contents = serde_json::to_vec(&LockFile { kind, pid, host, acquired_at: Utc::now() })?;
+
+        let deadline = Instant::now() + wait;
+        loop {
+            // Publish our lock first, then look for conflicts, so two processes never both succeed
+            write_atomically(&path, &contents)?;
+            let mut conflict = None;
+            for lock in list_locks(db_path)? {
+                if lock.path == path {
+                    continue;
+                }
+                if lock.stale {
+                    remove_lock(&lock)?;
+                } else if kind == LockKind::Exclusive || lock.kind == LockKind::Exclusive {
+                    conflict = Some(lock);
+                    break;
+                }
+            }
+
+            let Some(conflict) = conflict else {
+                break;
+            };
+            fs::remove_file(&path).map_err(|e| Error::io(&path, e))?;
+            if Instant::now() >= deadline {
+                return Err(Error::Locked(format!(
+                    "{}; run `backuptool unlock` if that process is no longer running", conflict
+                )));
+            }
+            // Stagger retries so competing exclusive lockers do not keep colliding
+            thread::sleep(RETRY_INTERVAL + Duration::from_millis((pid % 50) as u64));
+        }
+
+        let (stop, stopped) = mpsc::channel();
+        let heartbeat_path = path.clone();
+        let heartbeat = thread::spawn(move || {
+            while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(HEARTBEAT_INTERVAL) {
+                if let Ok(file) = fs::File::options().write(true).open(&heartbeat_path) {
+                    let _ = file.set_modified(SystemTime::now());
+                }
+            }
+        });
+
+        Ok(RepositoryLock { path, stop: Some(stop), heartbeat: Some(heartbeat) })
+    }
+}
+
+impl Drop for RepositoryLock {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(heartbeat) = self.heartbeat.take() {
+            let _ = heartbeat.join();
+        }
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Lists the locks currently recorded for the repository at `db_path`.
+pub fn list_locks(db_path: &Path) -> Result<Vec<LockInfo>> {
+    let dir = lock_dir(db_path);
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(Error::io(&dir, e)),
+    };
+
+    let local_host = hostname();
+    let mut locks = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|e| Error::io(&dir, e))?.path();
+        if path.extension().is_none_or(|ext| ext != "lock") {
+            continue;
+        }
+        // A lock released while we were listing is simply gone
+        let (contents, modified) = match fs::read(&path).and_then(|c| Ok((c, fs::metadata(&path)?.modified()?))) {
+            Ok(found) => found,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(Error::io(&path, e)),
+        };
+        let lock: LockFile = serde_json::from_slice(&contents)
+            .map_err(|e| Error::Corruption(format!("unreadable lock file {}: {}", path.display(), e)))?;
+
+        let age = SystemTime::now().duration_since(modified).unwrap_or_default();
+        let stale = age > STALE_AFTER || (lock.host == local_host && !process_running(lock.pid));
+        locks.push(LockInfo {
+            kind: lock.kind,
+            pid: lock.pid,
+            host: lock.host,
+            acquired_at: lock.acquired_at,
+            heartbeat: modified.into(),
+            stale,
+            path,
+        });
+    }
+
+    locks.sort_by_key(|lock| lock.acquired_at);
+    Ok(locks)
+}
+
+/// Deletes a lock's file, e.g. one left behind by a process that crashed.
+pub fn remove_lock(lock: &LockInfo) -> Result<()> {
+    match fs::remove_file(&lock.path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(Error::io(&lock.path, e)),
+        _ => Ok(()),
+    }
+}
+
+fn lock_dir(db_path: &Path) -> PathBuf {
+    let mut dir = db_path.as_os_str().to_owned();
+    dir.push(".locks");
+    PathBuf::from(dir)
+}
+
+fn write_atomically(path: &Path, contents: &[u8]) -> Result<()> {
+    let temp = path.with_extension("tmp");
+    fs::write(&temp, contents).map_err(|e| Error::io(&temp, e))?;
+    fs::rename(&temp, path).map_err(|e| Error::io(path, e))
+}
+
+fn hostname() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname").ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "localhost".to_string())
+}
+
+/// Whether a process with this pid exists on this host. Without `/proc` we cannot tell,
+/// so the process is assumed to be running and only the heartbeat can mark it stale.
+fn process_running(pid: u32) -> bool {
+    !Path::new("/proc/self").exists() || Path::new("/proc").join(pid.to_string()).exists()
+}
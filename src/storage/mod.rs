@@ -1,2 +1,13 @@
+pub mod chunker;
 pub mod database;
-pub use database::Database;
\ No newline at end of file
+pub mod lock;
+pub mod object_store;
+pub mod pack;
+pub mod s3;
+pub mod selector;
+
+pub use database::Database;
+pub use lock::{RepositoryLock, LockKind, LockInfo};
+pub use object_store::ObjectStore;
+pub use s3::S3Store;
+pub use selector::{SnapshotSelector, SnapshotFilter};
@@ -0,0 +1,24 @@
+use crate::error::Result;
+
+/// Minimal key/value interface over a remote object store.
+pub trait ObjectStore {
+    fn put(&self, key: &str, data: &[u8]) -> Result<()>;
+    fn get(&self, key: &str) -> Result<Vec<u8>>;
+    fn exists(&self, key: &str) -> Result<bool>;
+    fn delete(&self, key: &str) -> Result<()>;
+}
+
+/// Key under which a content block is stored, fanned out by the first two hash characters.
+pub fn content_key(content_hash: &str) -> String {
+    let fanout = content_hash.get(..2).unwrap_or(content_hash);
+    format!("data/{}/{}", fanout, content_hash)
+}
+
+/// Key under which a pack of small content blocks is stored, named by the hash of the pack.
+pub fn pack_key(pack_hash: &str) -> String {
+    let fanout = pack_hash.get(..2).unwrap_or(pack_hash);
+    format!("packs/{}/{}", fanout, pack_hash)
+}
+
+/// Key of the metadata index describing all snapshots in the remote.
+pub const INDEX_KEY: &str = "index/snapshots.json";
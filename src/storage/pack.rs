@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+
+/// Blobs smaller than this are aggregated into packs instead of being stored on their own.
+pub const PACK_BLOB_THRESHOLD: usize = 128 * 1024;
+
+/// Size at which a pack being built is considered full and gets written out.
+pub const PACK_TARGET_SIZE: usize = 4 * 1024 * 1024;
+
+/// Packs whose live bytes drop below this fraction of their size are rewritten during prune.
+pub const REPACK_LIVE_RATIO: f64 = 0.5;
+
+#[derive(Debug, Clone)]
+pub struct PackEntry {
+    pub hash: String,
+    pub offset: u64,
+    pub length: u64,
+}
+
+/// In-memory pack that small blobs are appended to until it reaches the target size.
+#[derive(Default)]
+pub struct PackBuilder {
+    data: Vec<u8>,
+    entries: Vec<PackEntry>,
+    index: HashMap<String, usize>,
+}
+
+impl PackBuilder {
+    pub fn add(&mut self, hash: &str, content: &[u8]) {
+        if self.index.contains_key(hash) {
+            return;
+        }
+
+        self.index.insert(hash.to_string(), self.entries.len());
+        self.entries.push(PackEntry {
+            hash: hash.to_string(),
+            offset: self.data.len() as u64,
+            length: content.len() as u64,
+        });
+        self.data.extend_from_slice(content);
+    }
+
+    pub fn contains(&self, hash: &str) -> bool {
+        self.index.contains_key(hash)
+    }
+
+    pub fn get(&self, hash: &str) -> Option<&[u8]> {
+        let entry = &self.entries[*self.index.get(hash)?];
+        let start = entry.offset as usize;
+        Some(&self.data[start..start + entry.length as usize])
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.data.len() >= PACK_TARGET_SIZE
+    }
+
+    /// Hands out the pack contents and resets the builder for the next pack.
+    pub fn take(&mut self) -> (Vec<u8>, Vec<PackEntry>) {
+        self.index.clear();
+        (std::mem::take(&mut self.data), std::mem::take(&mut self.entries))
+    }
+}
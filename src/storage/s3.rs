@@ -0,0 +1,257 @@
+use std::env;
+use std::io::Read;
+use std::thread;
+use std::time::Duration;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::{Sha256, Digest};
+
+use super::object_store::ObjectStore;
+use crate::error::{Error, Result};
+
+const DEFAULT_REGION: &str = "us-east-1";
+const MAX_ATTEMPTS: u32 = 5;
+const BASE_BACKOFF: Duration = Duration::from_millis(200);
+
+pub struct Credentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+}
+
+impl Credentials {
+    /// Reads the standard `AWS_ACCESS_KEY_ID`/`AWS_SECRET_ACCESS_KEY`/`AWS_SESSION_TOKEN` variables.
+    pub fn from_env() -> Result<Self> {
+        let access_key_id = env::var("AWS_ACCESS_KEY_ID")
+            .map_err(|_| Error::InvalidInput("AWS_ACCESS_KEY_ID is not set".to_string()))?;
+        let secret_access_key = env::var("AWS_SECRET_ACCESS_KEY")
+            .map_err(|_| Error::InvalidInput("AWS_SECRET_ACCESS_KEY is not set".to_string()))?;
+        let session_token = env::var("AWS_SESSION_TOKEN").ok().filter(|t| !t.is_empty());
+
+        Ok(Credentials { access_key_id, secret_access_key, session_token })
+    }
+}
+
+/// Object store speaking the S3 REST API with path-style addressing and SigV4 signing.
+pub struct S3Store {
+    agent: ureq::Agent,
+    endpoint: String,
+    host: String,
+    bucket: String,
+    prefix: String,
+    region: String,
+    credentials: Credentials,
+}
+
+impl S3Store {
+    /// Opens `s3://bucket/prefix`. The endpoint defaults to `AWS_ENDPOINT_URL`, then AWS itself.
+    pub fn from_url(url: &str, endpoint: Option<&str>) -> Result<Self> {
+        let location = url.strip_prefix("s3://")
+            .ok_or_else(|| Error::InvalidInput(format!("remote must be an s3:// URL: {}", url)))?;
+        let (bucket, prefix) = match location.split_once('/') {
+            Some((bucket, prefix)) => (bucket, prefix.trim_matches('/')),
+            None => (location, ""),
+        };
+        if bucket.is_empty() {
+            return Err(Error::InvalidInput(format!("remote URL is missing a bucket name: {}", url)));
+        }
+
+        let region = env::var("AWS_REGION")
+            .or_else(|_| env::var("AWS_DEFAULT_REGION"))
+            .unwrap_or_else(|_| DEFAULT_REGION.to_string());
+        let endpoint = match endpoint {
+            Some(endpoint) => endpoint.to_string(),
+            None => env::var("AWS_ENDPOINT_URL")
+                .unwrap_or_else(|_| format!("https://s3.{}.amazonaws.com", region)),
+        };
+
+        Self::new(&endpoint, bucket, prefix, &region, Credentials::from_env()?)
+    }
+
+    pub fn new(endpoint: &str, bucket: &str, prefix: &str, region: &str, credentials: Credentials) -> Result<Self> {
+        let endpoint = endpoint.trim_end_matches('/').to_string();
+        let host = endpoint.split_once("://")
+            .map(|(_, rest)| rest)
+            .ok_or_else(|| Error::InvalidInput(format!("endpoint must include a scheme: {}", endpoint)))?
+            .split('/')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(Duration::from_secs(10))
+            .timeout(Duration::from_secs(300))
+            .build();
+
+        Ok(S3Store {
+            agent,
+            endpoint,
+            host,
+            bucket: bucket.to_string(),
+            prefix: prefix.to_string(),
+            region: region.to_string(),
+            credentials,
+        })
+    }
+
+    fn object_path(&self, key: &str) -> String {
+        let key = if self.prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}/{}", self.prefix, key)
+        };
+        format!("/{}/{}", uri_encode(&self.bucket), uri_encode(&key))
+    }
+
+    /// Sends a signed request, retrying transport errors, throttling and server errors with backoff.
+    /// Returns `None` when the object (or bucket) does not exist.
+    fn send(&self, method: &str, key: &str, body: &[u8]) -> Result<Option<ureq::Response>> {
+        let path = self.object_path(key);
+        let url = format!("{}{}", self.endpoint, path);
+        let payload_hash = hex_sha256(body);
+
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let mut request = self.agent.request(method, &url);
+            for (name, value) in self.sign(method, &path, &payload_hash) {
+                request = request.set(&name, &value);
+            }
+
+            let result = if method == "PUT" {
+                request.send_bytes(body)
+            } else {
+                request.call()
+            };
+
+            let retryable = match result {
+                Ok(response) => return Ok(Some(response)),
+                Err(ureq::Error::Status(404, _)) => return Ok(None),
+                Err(ureq::Error::Status(code, response)) => {
+                    if code != 429 && code < 500 {
+                        let detail = response.into_string().unwrap_or_default();
+                        return Err(Error::Remote(format!(
+                            "S3 {} {} failed with status {}: {}", method, path, code, detail.trim()
+                        )));
+                    }
+                    format!("status {}", code)
+                }
+                Err(ureq::Error::Transport(e)) => e.to_string(),
+            };
+
+            if attempt >= MAX_ATTEMPTS {
+                return Err(Error::Remote(format!(
+                    "S3 {} {} failed after {} attempts: {}", method, path, attempt, retryable
+                )));
+            }
+            thread::sleep(BASE_BACKOFF * 2u32.pow(attempt - 1));
+        }
+    }
+
+    fn sign(&self, method: &str, path: &str, payload_hash: &str) -> Vec<(String, String)> {
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+
+        let mut headers = vec![
+            ("host".to_string(), self.host.clone()),
+            ("x-amz-content-sha256".to_string(), payload_hash.to_string()),
+            ("x-amz-date".to_string(), amz_date.clone()),
+        ];
+        if let Some(token) = &self.credentials.session_token {
+            headers.push(("x-amz-security-token".to_string(), token.clone()));
+        }
+
+        let canonical_headers: String = headers.iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value))
+            .collect();
+        let signed_headers = headers.iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_request = format!(
+            "{}\n{}\n\n{}\n{}\n{}",
+            method, path, canonical_headers, signed_headers, payload_hash
+        );
+
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date, scope, hex_sha256(canonical_request.as_bytes())
+        );
+
+        let key = hmac_sha256(format!("AWS4{}", self.credentials.secret_access_key).as_bytes(), date.as_bytes());
+        let key = hmac_sha256(&key, self.region.as_bytes());
+        let key = hmac_sha256(&key, b"s3");
+        let key = hmac_sha256(&key, b"aws4_request");
+        let signature = to_hex(&hmac_sha256(&key, string_to_sign.as_bytes()));
+
+        headers.push((
+            "authorization".to_string(),
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                self.credentials.access_key_id, scope, signed_headers, signature
+            ),
+        ));
+        // ureq derives the Host header from the URL itself
+        headers.retain(|(name, _)| name != "host");
+        headers
+    }
+}
+
+impl ObjectStore for S3Store {
+    fn put(&self, key: &str, data: &[u8]) -> Result<()> {
+        match self.send("PUT", key, data)? {
+            Some(_) => Ok(()),
+            None => Err(Error::Remote(format!("bucket {} does not exist", self.bucket))),
+        }
+    }
+
+    fn get(&self, key: &str) -> Result<Vec<u8>> {
+        match self.send("GET", key, &[])? {
+            Some(response) => {
+                let mut data = Vec::new();
+                response.into_reader().read_to_end(&mut data)
+                    .map_err(|e| Error::Remote(format!("failed to read object body {}: {}", key, e)))?;
+                Ok(data)
+            }
+            None => Err(Error::Remote(format!("object does not exist: {}", key))),
+        }
+    }
+
+    fn exists(&self, key: &str) -> Result<bool> {
+        Ok(self.send("HEAD", key, &[])?.is_some())
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        self.send("DELETE", key, &[])?;
+        Ok(())
+    }
+}
+
+fn uri_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex_sha256(data: &[u8]) -> String {
+    to_hex(&Sha256::digest(data))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,179 @@
+use std::fmt;
+use std::str::FromStr;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+
+use crate::error::{Error, Result};
+use super::database::SnapshotRecord;
+
+/// Fewest hex digits accepted as a snapshot UUID prefix.
+pub const MIN_UUID_PREFIX: usize = 4;
+/// Hex digits of the UUID shown as a snapshot's short ID.
+pub const SHORT_ID_LEN: usize = 8;
+
+/// Identifies a snapshot by number, UUID prefix or its position among matching snapshots.
+///
+/// Accepted forms: `42`, `3f2a1b4c`, `uuid:1234`, `latest`, `latest~2`, `@2026-10-01T00:00`,
+/// `tag:NAME`, `path:/srv/data`, and the tag/path filters followed by `:latest`, `:latest~N`
+/// or `:@TIME`. Digits are a snapshot number unless they start with a zero or are at least
+/// `SHORT_ID_LEN` long, so every short ID `list` shows reads back as a UUID prefix. Shorter
+/// all-digit UUID prefixes need the `uuid:` form.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SnapshotSelector {
+    Id(u32),
+    /// A unique prefix of the snapshot's UUID, as lowercase hex digits without dashes.
+    Uuid(String),
+    /// The newest matching snapshot, skipping `skip` newer ones.
+    Latest { filter: SnapshotFilter, skip: usize },
+    /// The newest matching snapshot taken at or before `time`.
+    AtOrBefore { filter: SnapshotFilter, time: DateTime<Utc> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SnapshotFilter {
+    All,
+    Tag(String),
+    Path(String),
+}
+
+impl SnapshotFilter {
+    pub fn matches(&self, snapshot: &SnapshotRecord) -> bool {
+        match self {
+            SnapshotFilter::All => true,
+            SnapshotFilter::Tag(tag) => snapshot.tags.contains(tag),
+            SnapshotFilter::Path(path) => snapshot.sources.iter()
+                .any(|source| trim_separators(&source.path) == trim_separators(path)),
+        }
+    }
+}
+
+fn trim_separators(path: &str) -> &str {
+    match path.trim_end_matches('/') {
+        "" => path,
+        trimmed => trimmed,
+    }
+}
+
+impl FromStr for SnapshotSelector {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some(id) = snapshot_number(s) {
+            return Ok(SnapshotSelector::Id(id));
+        }
+        if let Some(prefix) = s.strip_prefix("uuid:") {
+            return uuid_prefix(prefix).map(SnapshotSelector::Uuid).ok_or_else(|| invalid(s));
+        }
+        if let Some(prefix) = uuid_prefix(s) {
+            return Ok(SnapshotSelector::Uuid(prefix));
+        }
+
+        let (filter, position) = if let Some(tag) = s.strip_prefix("tag:") {
+            let (tag, position) = split_position(tag);
+            (SnapshotFilter::Tag(tag.to_string()), position)
+        } else if let Some(path) = s.strip_prefix("path:") {
+            let (path, position) = split_position(path);
+            (SnapshotFilter::Path(path.to_string()), position)
+        } else {
+            (SnapshotFilter::All, s)
+        };
+
+        if matches!(&filter, SnapshotFilter::Tag(v) | SnapshotFilter::Path(v) if v.is_empty()) {
+            return Err(invalid(s));
+        }
+
+        if let Some(time) = position.strip_prefix('@') {
+            let time = parse_time(time).ok_or_else(|| invalid(s))?;
+            return Ok(SnapshotSelector::AtOrBefore { filter, time });
+        }
+
+        let skip = match position.strip_prefix("latest") {
+            Some("") => 0,
+            Some(rest) => rest.strip_prefix('~')
+                .and_then(|n| n.parse().ok())
+                .ok_or_else(|| invalid(s))?,
+            None => return Err(invalid(s)),
+        };
+
+        Ok(SnapshotSelector::Latest { filter, skip })
+    }
+}
+
+/// Parses `s` as a snapshot number, unless it could be a short ID or other UUID prefix.
+pub fn snapshot_number(s: &str) -> Option<u32> {
+    let uuid_like = s.len() >= SHORT_ID_LEN || (s.starts_with('0') && s.len() >= MIN_UUID_PREFIX);
+    if uuid_like && s.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+/// Normalises a UUID prefix such as `3F2A-1B` to `3f2a1b`, if `s` is one.
+fn uuid_prefix(s: &str) -> Option<String> {
+    let prefix: String = s.chars().filter(|c| *c != '-').map(|c| c.to_ascii_lowercase()).collect();
+    let valid = prefix.len() >= MIN_UUID_PREFIX && prefix.len() <= 32 && prefix.chars().all(|c| c.is_ascii_hexdigit());
+    valid.then_some(prefix)
+}
+
+/// The short form of a snapshot UUID shown next to its number.
+pub fn short_id(uuid: &str) -> String {
+    uuid.chars().filter(|c| *c != '-').take(SHORT_ID_LEN).collect()
+}
+
+/// Splits `value:latest~N` or `value:@TIME` into the filter value and the position,
+/// which defaults to `latest`.
+fn split_position(s: &str) -> (&str, &str) {
+    // Timestamps contain colons, so look for the first `:@` rather than the last colon
+    if let Some(i) = s.find(":@") {
+        return (&s[..i], &s[i + 1..]);
+    }
+    if let Some(i) = s.rfind(":latest") {
+        let position = &s[i + 1..];
+        if position == "latest" || position.strip_prefix("latest~").is_some_and(|n| n.parse::<usize>().is_ok()) {
+            return (&s[..i], position);
+        }
+    }
+    (s, "latest")
+}
+
+/// Parses an RFC 3339 timestamp, or a UTC date or date and time without an offset.
+fn parse_time(s: &str) -> Option<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Some(time.with_timezone(&Utc));
+    }
+    for format in ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"] {
+        if let Ok(time) = NaiveDateTime::parse_from_str(s, format) {
+            return Some(time.and_utc());
+        }
+    }
+    NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|time| time.and_utc())
+}
+
+fn invalid(s: &str) -> Error {
+    Error::InvalidInput(format!(
+        "invalid snapshot selector {:?} (expected a number, an ID prefix, latest, latest~N, @TIME, tag:NAME or path:DIR)", s
+    ))
+}
+
+impl fmt::Display for SnapshotFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotFilter::All => Ok(()),
+            SnapshotFilter::Tag(tag) => write!(f, "tag:{}:", tag),
+            SnapshotFilter::Path(path) => write!(f, "path:{}:", path),
+        }
+    }
+}
+
+impl fmt::Display for SnapshotSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotSelector::Id(id) => write!(f, "{}", id),
+            SnapshotSelector::Uuid(prefix) => write!(f, "uuid:{}", prefix),
+            SnapshotSelector::Latest { filter, skip: 0 } => write!(f, "{}latest", filter),
+            SnapshotSelector::Latest { filter, skip } => write!(f, "{}latest~{}", filter, skip),
+            SnapshotSelector::AtOrBefore { filter, time } => write!(f, "{}@{}", filter, time.to_rfc3339()),
+        }
+    }
+}
@@ -4,4 +4,20 @@ pub fn hash_content(content: &[u8]) -> String {
     let mut hasher = Sha256::new();
     hasher.update(content);
     format!("{:x}", hasher.finalize())
-}
\ No newline at end of file
+}
+
+/// Incremental form of `hash_content` for data that arrives in pieces.
+#[derive(Default)]
+pub struct ContentHasher {
+    hasher: Sha256,
+}
+
+impl ContentHasher {
+    pub fn update(&mut self, content: &[u8]) {
+        self.hasher.update(content);
+    }
+
+    pub fn finish(self) -> String {
+        format!("{:x}", self.hasher.finalize())
+    }
+}
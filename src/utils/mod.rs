@@ -1,5 +1,5 @@
 pub mod hash;
 pub mod path;
 
-pub use hash::hash_content;
-pub use path::relative_path;
\ No newline at end of file
+pub use hash::{hash_content, ContentHasher};
+pub use path::{relative_path, Excludes};
\ No newline at end of file
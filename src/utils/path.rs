@@ -1,5 +1,6 @@
 use std::path::{Path, PathBuf};
-use anyhow::Result;
+use glob::{MatchOptions, Pattern};
+use crate::error::{Error, Result};
 
 pub fn relative_path(path: &Path, base: &Path) -> Result<PathBuf> {
     let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
@@ -9,4 +10,37 @@ pub fn relative_path(path: &Path, base: &Path) -> Result<PathBuf> {
         Ok(relative) => Ok(relative.to_path_buf()),
         Err(_) => Ok(path),
     }
-}
\ No newline at end of file
+}
+
+/// Glob patterns for paths left out of snapshots. A pattern without a `/` matches a file or
+/// directory name at any depth; one with a `/` matches the path from the source root.
+/// `*` stays within one path component and `**` spans any number of them.
+pub struct Excludes {
+    names: Vec<Pattern>,
+    paths: Vec<Pattern>,
+}
+
+impl Excludes {
+    pub fn new(patterns: &[String]) -> Result<Self> {
+        let mut excludes = Excludes { names: Vec::new(), paths: Vec::new() };
+        for pattern in patterns {
+            let trimmed = pattern.trim_start_matches('/').trim_end_matches('/');
+            let compiled = Pattern::new(trimmed)
+                .map_err(|e| Error::InvalidInput(format!("invalid exclude pattern {:?}: {}", pattern, e)))?;
+            if trimmed.contains('/') {
+                excludes.paths.push(compiled);
+            } else {
+                excludes.names.push(compiled);
+            }
+        }
+        Ok(excludes)
+    }
+
+    /// Whether `relative`, a path below a source root, is excluded.
+    pub fn is_excluded(&self, relative: &Path) -> bool {
+        let options = MatchOptions { require_literal_separator: true, ..MatchOptions::new() };
+        let name_matches = relative.file_name()
+            .is_some_and(|name| self.names.iter().any(|p| p.matches_with(&name.to_string_lossy(), options)));
+        name_matches || self.paths.iter().any(|p| p.matches_path_with(relative, options))
+    }
+}
@@ -1,6 +1,7 @@
 use std::fs;
+use std::io::Write;
 use std::path::Path;
-use std::process::Command;
+use std::process::{Command, Stdio};
 use tempfile::TempDir;
 
 pub struct TestEnvironment {
@@ -16,6 +17,7 @@ impl TestEnvironment {
         let db_path = temp_dir.path().join("test.db");
         
         fs::create_dir_all(&test_data_dir).unwrap();
+        init_repository(&db_path);
         
         Self {
             temp_dir,
@@ -29,13 +31,48 @@ impl TestEnvironment {
     }
 }
 
+pub fn init_repository(db_path: &Path) {
+    let output = run_backuptool(&["init", "--database", db_path.to_str().unwrap()]);
+    assert!(output.status.success(), "Init failed: {}", String::from_utf8_lossy(&output.stderr));
+}
+
+/// A backuptool command that sees neither the user's config file nor their default repository.
+pub fn backuptool_command(config_home: &Path) -> Command {
+    let mut command = Command::new("./target/debug/backuptool");
+    command.env("XDG_CONFIG_HOME", config_home).env_remove("BACKUPTOOL_REPOSITORY");
+    command
+}
+
 pub fn run_backuptool(args: &[&str]) -> std::process::Output {
-    Command::new("./target/debug/backuptool")
+    let config_home = TempDir::new().unwrap();
+    backuptool_command(config_home.path())
+        .args(args)
+        .output()
+        .expect("Failed to execute backuptool command")
+}
+
+pub fn run_backuptool_with_env(args: &[&str], envs: &[(&str, &str)]) -> std::process::Output {
+    let config_home = TempDir::new().unwrap();
+    backuptool_command(config_home.path())
         .args(args)
+        .envs(envs.iter().copied())
         .output()
         .expect("Failed to execute backuptool command")
 }
 
+pub fn run_backuptool_with_stdin(args: &[&str], input: &[u8]) -> std::process::Output {
+    let config_home = TempDir::new().unwrap();
+    let mut child = backuptool_command(config_home.path())
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to execute backuptool command");
+    child.stdin.take().unwrap().write_all(input).expect("Failed to write stdin");
+    child.wait_with_output().expect("Failed to wait for backuptool command")
+}
+
 pub fn create_test_files(dir: &Path) -> std::io::Result<()> {
     fs::write(dir.join("file1.txt"), "Hello World")?;
     fs::write(dir.join("file2.txt"), "Another file")?;
@@ -44,10 +81,31 @@ pub fn create_test_files(dir: &Path) -> std::io::Result<()> {
     Ok(())
 }
 
+/// Creates a file with mode 000, or returns `None` when running as root, which can read it anyway.
+#[cfg(unix)]
+pub fn create_unreadable_file(dir: &Path, name: &str) -> Option<std::path::PathBuf> {
+    use std::os::unix::fs::PermissionsExt;
+    let path = dir.join(name);
+    fs::write(&path, "secret").unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o000)).unwrap();
+    fs::read(&path).is_err().then_some(path)
+}
+
 pub fn create_binary_file(dir: &Path, name: &str, content: &[u8]) -> std::io::Result<()> {
     fs::write(dir.join(name), content)
 }
 
+/// Deterministic pseudo-random bytes, so chunk boundaries are the same on every run.
+pub fn pseudo_random(len: usize, seed: u64) -> Vec<u8> {
+    let mut state = seed;
+    (0..len).map(|_| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state >> 24) as u8
+    }).collect()
+}
+
 pub fn verify_file_content(path: &Path, expected: &str) {
     let content = fs::read_to_string(path)
         .unwrap_or_else(|_| panic!("Failed to read file: {}", path.display()));
@@ -0,0 +1,111 @@
+use crate::common::*;
+use std::fs;
+
+fn write_config(env: &TestEnvironment, contents: &str) -> String {
+    let path = env.temp_dir.path().join("config.toml");
+    fs::write(&path, contents).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+fn snapshot_ids(output: &std::process::Output) -> Vec<u64> {
+    let snapshots: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    snapshots.as_array().unwrap().iter().map(|s| s["id"].as_u64().unwrap()).collect()
+}
+
+#[test]
+fn test_commands_refuse_missing_repository() {
+    let env = TestEnvironment::new();
+    create_test_files(&env.test_data_dir).unwrap();
+    let missing = env.temp_dir.path().join("typo.db");
+
+    let output = run_backuptool(&[
+        "snapshot",
+        "--target-directory", env.test_data_dir.to_str().unwrap(),
+        "--database", missing.to_str().unwrap()
+    ]);
+    assert_eq!(output.status.code(), Some(12));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("backuptool init"));
+    assert!(!missing.exists(), "No database should be created outside init");
+    assert!(!env.temp_dir.path().join("typo.db.locks").exists());
+
+    let output = run_backuptool(&["init", "--database", env.db_path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(2), "init must not reuse an existing repository");
+}
+
+#[test]
+fn test_config_repositories_and_excludes() {
+    let env = TestEnvironment::new();
+    create_test_files(&env.test_data_dir).unwrap();
+    fs::write(env.test_data_dir.join("scratch.tmp"), "temporary").unwrap();
+    fs::create_dir_all(env.test_data_dir.join("cache/deep")).unwrap();
+    fs::write(env.test_data_dir.join("cache/deep/blob"), "cached").unwrap();
+    fs::write(env.test_data_dir.join("subdir/skip.log"), "log").unwrap();
+    let config = write_config(&env, r#"
+excludes = ["*.tmp"]
+
+[repositories.main]
+path = "main.db"
+excludes = ["cache"]
+"#);
+
+    let output = run_backuptool(&["init", "--repository", "main", "--config", &config]);
+    assert!(output.status.success(), "Init failed: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(env.temp_dir.path().join("main.db").is_file(), "Repository paths are relative to the config file");
+
+    let output = run_backuptool_with_env(&[
+        "snapshot",
+        "--target-directory", env.test_data_dir.to_str().unwrap(),
+        "--exclude", "subdir/*.log",
+        "--config", &config
+    ], &[("BACKUPTOOL_REPOSITORY", "main")]);
+    assert!(output.status.success(), "Snapshot failed: {}", String::from_utf8_lossy(&output.stderr));
+
+    let restore_dir = env.restore_dir("config");
+    let output = run_backuptool(&[
+        "restore",
+        "--snapshot-number", "1",
+        "--output-directory", restore_dir.to_str().unwrap(),
+        "--repository", "main",
+        "--config", &config
+    ]);
+    assert!(output.status.success());
+    verify_file_content(&restore_dir.join("file1.txt"), "Hello World");
+    verify_file_content(&restore_dir.join("subdir/file3.txt"), "Nested file");
+    verify_file_not_exists(&restore_dir.join("scratch.tmp"));
+    verify_file_not_exists(&restore_dir.join("cache/deep/blob"));
+    verify_file_not_exists(&restore_dir.join("subdir/skip.log"));
+
+    // A path in the environment works without any config
+    let db = env.db_path.to_str().unwrap();
+    let output = run_backuptool_with_env(&["list", "--json"], &[("BACKUPTOOL_REPOSITORY", db)]);
+    assert!(output.status.success());
+    assert!(snapshot_ids(&output).is_empty());
+
+    let output = run_backuptool(&["list", "--repository", "other", "--config", &config]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_prune_applies_configured_retention() {
+    let env = TestEnvironment::new();
+    create_test_files(&env.test_data_dir).unwrap();
+    let db = env.db_path.to_str().unwrap();
+
+    let output = run_backuptool(&["prune", "--database", db]);
+    assert_eq!(output.status.code(), Some(2), "Without a policy a bare prune has nothing to do");
+
+    for _ in 0..3 {
+        run_backuptool(&["snapshot", "--target-directory", env.test_data_dir.to_str().unwrap(), "--database", db]);
+    }
+    // A configured age is checked like --older-than
+    let config = write_config(&env, "[retention]\nolder_than = \"-1d\"\n");
+    let output = run_backuptool(&["prune", "--database", db, "--config", &config]);
+    assert_eq!(output.status.code(), Some(2), "Negative retention age must be refused");
+
+    let config = write_config(&env, "[retention]\nkeep_last = 1\n");
+    let output = run_backuptool(&["prune", "--database", db, "--config", &config]);
+    assert!(output.status.success(), "Prune failed: {}", String::from_utf8_lossy(&output.stderr));
+
+    let output = run_backuptool(&["list", "--json", "--database", db]);
+    assert_eq!(snapshot_ids(&output), vec![3]);
+}
@@ -0,0 +1,116 @@
+use crate::common::*;
+use std::fs;
+
+fn snapshot(env: &TestEnvironment, extra: &[&str]) {
+    let mut args = vec![
+        "snapshot",
+        "--target-directory", env.test_data_dir.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap()
+    ];
+    args.extend(extra);
+    let output = run_backuptool(&args);
+    assert!(output.status.success(), "Snapshot failed: {}", String::from_utf8_lossy(&output.stderr));
+}
+
+fn copy(args: &[&str]) -> serde_json::Value {
+    let mut full = vec!["copy", "--json"];
+    full.extend(args);
+    let output = run_backuptool(&full);
+    assert!(output.status.success(), "Copy failed: {}", String::from_utf8_lossy(&output.stderr));
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+fn list(db: &str) -> Vec<serde_json::Value> {
+    let output = run_backuptool(&["list", "--json", "--database", db]);
+    serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap().as_array().unwrap().clone()
+}
+
+#[test]
+fn test_copy_transfers_only_missing_snapshots_and_content() {
+    let env = TestEnvironment::new();
+    create_test_files(&env.test_data_dir).unwrap();
+    let source = env.db_path.to_str().unwrap();
+    let offsite = env.temp_dir.path().join("offsite.db");
+    let offsite = offsite.to_str().unwrap();
+    init_repository(offsite.as_ref());
+
+    snapshot(&env, &["--tag", "nightly", "--message", "first"]);
+    fs::write(env.test_data_dir.join("file1.txt"), "Changed").unwrap();
+    snapshot(&env, &[]);
+
+    let report = copy(&["--from", source, "--to", offsite]);
+    assert_eq!(report["copied"].as_array().unwrap().len(), 2);
+    assert_eq!(report["blocks_copied"], 4, "Unchanged files share their blocks");
+
+    let (original, copied) = (list(source), list(offsite));
+    assert_eq!(copied.len(), 2);
+    for (original, copied) in original.iter().zip(&copied) {
+        assert_eq!(original["timestamp"], copied["timestamp"]);
+        assert_eq!(original["tags"], copied["tags"]);
+        assert_eq!(original["message"], copied["message"]);
+    }
+
+    fs::write(env.test_data_dir.join("file2.txt"), "Changed again").unwrap();
+    snapshot(&env, &[]);
+    let report = copy(&["--from", source, "--to", offsite]);
+    assert_eq!(report["skipped"], serde_json::json!([1, 2]));
+    assert_eq!(report["copied"][0]["source_id"], 3);
+    assert_eq!(report["blocks_copied"], 1);
+    assert_eq!(report["bytes_copied"], "Changed again".len());
+
+    let restore_dir = env.restore_dir("offsite");
+    let output = run_backuptool(&[
+        "restore",
+        "--snapshot-number", "3",
+        "--output-directory", restore_dir.to_str().unwrap(),
+        "--database", offsite
+    ]);
+    assert!(output.status.success());
+    verify_file_content(&restore_dir.join("file1.txt"), "Changed");
+    verify_file_content(&restore_dir.join("file2.txt"), "Changed again");
+    verify_file_content(&restore_dir.join("subdir/file3.txt"), "Nested file");
+}
+
+#[test]
+fn test_copy_selected_snapshots_back_and_forth() {
+    let env = TestEnvironment::new();
+    create_test_files(&env.test_data_dir).unwrap();
+    let source = env.db_path.to_str().unwrap();
+    let offsite = env.temp_dir.path().join("offsite.db");
+    let offsite = offsite.to_str().unwrap();
+    init_repository(offsite.as_ref());
+    snapshot(&env, &[]);
+    snapshot(&env, &["--tag", "keep"]);
+
+    // Without --from the selected repository is the source
+    let report = copy(&["--to", offsite, "--snapshot", "tag:keep", "--database", source]);
+    assert_eq!(report["copied"][0]["source_id"], 2);
+    assert_eq!(report["copied"][0]["destination_id"], 1);
+    assert_eq!(report["blocks_copied"], 3);
+
+    // The copy keeps its UUID, so copying it back finds it already there
+    let report = copy(&["--from", offsite, "--to", source]);
+    assert_eq!(report["copied"], serde_json::json!([]));
+    assert_eq!(report["skipped"], serde_json::json!([1]));
+    assert_eq!(list(source).len(), 2);
+
+    let output = run_backuptool(&["copy", "--from", source, "--to", source]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_copy_refuses_paths_outside_the_snapshot() {
+    let env = TestEnvironment::new();
+    create_test_files(&env.test_data_dir).unwrap();
+    snapshot(&env, &[]);
+    let conn = rusqlite::Connection::open(&env.db_path).unwrap();
+    conn.execute("UPDATE files SET path = '/etc/escape' WHERE path = 'file1.txt'", []).unwrap();
+    drop(conn);
+    let offsite = env.temp_dir.path().join("offsite.db");
+    let offsite = offsite.to_str().unwrap();
+    init_repository(offsite.as_ref());
+
+    let output = run_backuptool(&["copy", "--from", env.db_path.to_str().unwrap(), "--to", offsite]);
+    assert_eq!(output.status.code(), Some(5), "Copy should refuse the path: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(list(offsite).is_empty());
+}
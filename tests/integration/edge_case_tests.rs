@@ -186,4 +186,21 @@ fn test_concurrent_modifications() {
     
     let stdout = String::from_utf8_lossy(&output.stdout);
     assert!(stdout.matches('\n').count() >= 10);
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_newer_schema_version_is_refused() {
+    let env = TestEnvironment::new();
+
+    let conn = rusqlite::Connection::open(&env.db_path).unwrap();
+    conn.pragma_update(None, "user_version", 999).unwrap();
+    drop(conn);
+
+    let output = run_backuptool(&[
+        "list",
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+
+    assert_eq!(output.status.code(), Some(8), "Schema mismatch should exit with code 8");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("schema version 999"));
+}
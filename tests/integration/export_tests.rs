@@ -0,0 +1,90 @@
+use crate::common::*;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Read;
+
+fn snapshot(env: &TestEnvironment) {
+    create_test_files(&env.test_data_dir).unwrap();
+    let output = run_backuptool(&[
+        "snapshot",
+        "--target-directory", env.test_data_dir.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+    assert!(output.status.success());
+}
+
+fn export(env: &TestEnvironment, format: &str, output: &str) -> std::process::Output {
+    run_backuptool(&[
+        "export",
+        "--snapshot", "latest",
+        "--format", format,
+        "--output", output,
+        "--database", env.db_path.to_str().unwrap()
+    ])
+}
+
+fn tar_entries(data: &[u8]) -> BTreeMap<String, String> {
+    let mut archive = tar::Archive::new(data);
+    archive.entries().unwrap().map(|entry| {
+        let mut entry = entry.unwrap();
+        let path = entry.path().unwrap().to_string_lossy().to_string();
+        let mut content = String::new();
+        entry.read_to_string(&mut content).unwrap();
+        (path, content)
+    }).collect()
+}
+
+fn expected_entries() -> BTreeMap<String, String> {
+    [("file1.txt", "Hello World"), ("file2.txt", "Another file"), ("subdir/file3.txt", "Nested file")]
+        .into_iter()
+        .map(|(path, content)| (path.to_string(), content.to_string()))
+        .collect()
+}
+
+#[test]
+fn test_export_tar() {
+    let env = TestEnvironment::new();
+    snapshot(&env);
+
+    let archive = env.temp_dir.path().join("snapshot.tar");
+    let output = export(&env, "tar", archive.to_str().unwrap());
+    assert!(output.status.success(), "Export failed: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Files exported: 3"));
+
+    assert_eq!(tar_entries(&fs::read(&archive).unwrap()), expected_entries());
+}
+
+#[test]
+fn test_export_tar_zst_to_stdout() {
+    let env = TestEnvironment::new();
+    snapshot(&env);
+
+    let output = export(&env, "tar.zst", "-");
+    assert!(output.status.success(), "Export failed: {}", String::from_utf8_lossy(&output.stderr));
+
+    let tar = zstd::decode_all(output.stdout.as_slice()).unwrap();
+    assert_eq!(tar_entries(&tar), expected_entries());
+}
+
+#[test]
+fn test_export_zip() {
+    let env = TestEnvironment::new();
+    snapshot(&env);
+
+    let archive_path = env.temp_dir.path().join("snapshot.zip");
+    let output = export(&env, "zip", archive_path.to_str().unwrap());
+    assert!(output.status.success(), "Export failed: {}", String::from_utf8_lossy(&output.stderr));
+
+    let mut archive = zip::ZipArchive::new(fs::File::open(&archive_path).unwrap()).unwrap();
+    let mut entries = BTreeMap::new();
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i).unwrap();
+        let mut content = String::new();
+        file.read_to_string(&mut content).unwrap();
+        entries.insert(file.name().to_string(), content);
+    }
+    assert_eq!(entries, expected_entries());
+
+    let output = export(&env, "zip", "-");
+    assert_eq!(output.status.code(), Some(2), "Zip cannot be streamed to stdout");
+}
@@ -0,0 +1,162 @@
+use crate::common::*;
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+const SCRIPT_MTIME: u64 = 1_600_000_000;
+const NOTES_MTIME: u64 = 1_700_000_000;
+
+/// A tar archive with a directory entry, an executable script and a private file.
+fn build_tar() -> Vec<u8> {
+    let mut builder = tar::Builder::new(Vec::new());
+
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Directory);
+    header.set_size(0);
+    header.set_mode(0o755);
+    builder.append_data(&mut header, "./bin/", std::io::empty()).unwrap();
+
+    for (path, content, mode, mtime) in [
+        ("./bin/run.sh", "#!/bin/sh\necho hi\n", 0o755, SCRIPT_MTIME),
+        ("notes.txt", "private notes", 0o600, NOTES_MTIME),
+    ] {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_size(content.len() as u64);
+        header.set_mode(mode);
+        header.set_mtime(mtime);
+        builder.append_data(&mut header, path, content.as_bytes()).unwrap();
+    }
+
+    builder.into_inner().unwrap()
+}
+
+fn import(env: &TestEnvironment, archive: &Path) -> std::process::Output {
+    run_backuptool(&[
+        "import",
+        "--archive", archive.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap()
+    ])
+}
+
+fn assert_metadata(path: &Path, mode: u32, mtime: u64) {
+    let metadata = fs::metadata(path).unwrap();
+    assert_eq!(metadata.permissions().mode() & 0o7777, mode, "mode of {:?}", path);
+    assert_eq!(metadata.modified().unwrap(), UNIX_EPOCH + Duration::from_secs(mtime), "mtime of {:?}", path);
+}
+
+#[test]
+fn test_import_tar_gz_keeps_modes_and_mtimes() {
+    let env = TestEnvironment::new();
+    let archive = env.temp_dir.path().join("backup.tar.gz");
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(&build_tar()).unwrap();
+    fs::write(&archive, encoder.finish().unwrap()).unwrap();
+
+    let output = import(&env, &archive);
+    assert!(output.status.success(), "Import failed: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Files processed: 2"));
+
+    let restore_dir = env.restore_dir("import");
+    let output = run_backuptool(&[
+        "restore",
+        "--snapshot-number", "1",
+        "--output-directory", restore_dir.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+    assert!(output.status.success(), "Restore failed: {}", String::from_utf8_lossy(&output.stderr));
+
+    verify_file_content(&restore_dir.join("bin/run.sh"), "#!/bin/sh\necho hi\n");
+    verify_file_content(&restore_dir.join("notes.txt"), "private notes");
+    assert_metadata(&restore_dir.join("bin/run.sh"), 0o755, SCRIPT_MTIME);
+    assert_metadata(&restore_dir.join("notes.txt"), 0o600, NOTES_MTIME);
+
+    // Exporting the snapshot again reproduces the archive's metadata
+    let output = run_backuptool(&[
+        "export",
+        "--snapshot", "1",
+        "--format", "tar",
+        "--output", "-",
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+    assert!(output.status.success());
+    let mut exported = tar::Archive::new(output.stdout.as_slice());
+    let mut entries = Vec::new();
+    for entry in exported.entries().unwrap() {
+        let entry = entry.unwrap();
+        let header = entry.header();
+        let path = entry.path().unwrap().to_string_lossy().to_string();
+        entries.push((path, header.mode().unwrap(), header.mtime().unwrap()));
+    }
+    assert_eq!(entries, vec![
+        ("bin/run.sh".to_string(), 0o755, SCRIPT_MTIME),
+        ("notes.txt".to_string(), 0o600, NOTES_MTIME),
+    ]);
+}
+
+#[test]
+fn test_import_plain_and_zst_archives_share_content() {
+    let env = TestEnvironment::new();
+    let plain = env.temp_dir.path().join("backup.tar");
+    let compressed = env.temp_dir.path().join("backup.tar.zst");
+    fs::write(&plain, build_tar()).unwrap();
+    fs::write(&compressed, zstd::encode_all(build_tar().as_slice(), 0).unwrap()).unwrap();
+
+    let output = import(&env, &plain);
+    assert!(output.status.success(), "Import failed: {}", String::from_utf8_lossy(&output.stderr));
+
+    let output = run_backuptool(&[
+        "--json",
+        "import",
+        "--archive", compressed.to_str().unwrap(),
+        "--tag", "imported",
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+    assert!(output.status.success(), "Import failed: {}", String::from_utf8_lossy(&output.stderr));
+    let summary: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(summary["snapshot_id"], 2);
+    assert_eq!(summary["files_processed"], 2);
+    assert_eq!(summary["deduplicated_files"], 2);
+    assert_eq!(summary["bytes_added"], 0);
+    assert_eq!(summary["sources"][0]["name"], "backup.tar.zst");
+    assert_eq!(summary["tags"], serde_json::json!(["imported"]));
+
+    let output = run_backuptool(&["cat", "--snapshot", "tag:imported", "notes.txt", "--database", env.db_path.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"private notes");
+}
+
+#[test]
+fn test_import_truncated_archive_records_failed_entries() {
+    let env = TestEnvironment::new();
+    let archive = env.temp_dir.path().join("truncated.tar");
+    let mut tar = build_tar();
+    // Cut the archive a few bytes into the content of notes.txt, its last entry
+    let notes_data = tar.windows(13).position(|w| w == b"private notes").unwrap();
+    tar.truncate(notes_data + 5);
+    fs::write(&archive, tar).unwrap();
+
+    let output = run_backuptool(&[
+        "import",
+        "--archive", archive.to_str().unwrap(),
+        "--strict",
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+    assert_eq!(output.status.code(), Some(7), "Strict import should stop at the broken entry");
+
+    let output = import(&env, &archive);
+    assert_eq!(output.status.code(), Some(10), "Partial import should exit with code 10");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Failed to process file notes.txt"));
+
+    let conn = rusqlite::Connection::open(&env.db_path).unwrap();
+    let files: Vec<String> = conn.prepare("SELECT path FROM files").unwrap()
+        .query_map([], |row| row.get(0)).unwrap()
+        .collect::<Result<_, _>>().unwrap();
+    assert_eq!(files, vec!["bin/run.sh"], "Entries before the damage are imported");
+    let errors: Vec<String> = conn.prepare("SELECT path FROM snapshot_errors").unwrap()
+        .query_map([], |row| row.get(0)).unwrap()
+        .collect::<Result<_, _>>().unwrap();
+    assert_eq!(errors[0], "notes.txt");
+}
@@ -0,0 +1,53 @@
+use crate::common::*;
+
+#[test]
+fn test_init_records_repository_settings() {
+    let env = TestEnvironment::new();
+    let other = env.temp_dir.path().join("other.db");
+
+    let output = run_backuptool(&["init", "--json", "--database", other.to_str().unwrap()]);
+    assert!(output.status.success(), "Init failed: {}", String::from_utf8_lossy(&output.stderr));
+    let info: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(info["hash_algorithm"], "sha256");
+    assert_eq!(info["chunker"], "gear");
+    assert_eq!(info["compression"], "none");
+    assert!(info["chunk_min_size"].as_u64().unwrap() < info["chunk_max_size"].as_u64().unwrap());
+
+    let conn = rusqlite::Connection::open(&env.db_path).unwrap();
+    let first_id: String = conn.query_row(
+        "SELECT value FROM config WHERE key = 'repository_id'", [], |row| row.get(0)
+    ).unwrap();
+    assert_eq!(first_id.len(), 36);
+    assert_ne!(info["repository_id"], first_id.as_str(), "Every repository gets its own ID");
+}
+
+#[test]
+fn test_uninitialised_databases_are_refused() {
+    let env = TestEnvironment::new();
+    create_test_files(&env.test_data_dir).unwrap();
+    let db = env.db_path.to_str().unwrap();
+    run_backuptool(&["snapshot", "--target-directory", env.test_data_dir.to_str().unwrap(), "--database", db]);
+
+    // A database written before repositories had settings must be adopted with init
+    let conn = rusqlite::Connection::open(&env.db_path).unwrap();
+    conn.execute("DROP TABLE config", []).unwrap();
+    drop(conn);
+    let output = run_backuptool(&["list", "--database", db]);
+    assert_eq!(output.status.code(), Some(12));
+
+    let output = run_backuptool(&["init", "--database", db]);
+    assert!(output.status.success(), "Init failed: {}", String::from_utf8_lossy(&output.stderr));
+    let output = run_backuptool(&["list", "--json", "--database", db]);
+    assert!(output.status.success());
+    let snapshots: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(snapshots.as_array().unwrap().len(), 1, "Adopting a database keeps its snapshots");
+
+    // Some other SQLite database is neither opened nor taken over
+    let foreign = env.temp_dir.path().join("foreign.db");
+    rusqlite::Connection::open(&foreign).unwrap()
+        .execute("CREATE TABLE notes (body TEXT)", []).unwrap();
+    let output = run_backuptool(&["list", "--database", foreign.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(12));
+    let output = run_backuptool(&["init", "--database", foreign.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(2));
+}
@@ -0,0 +1,87 @@
+use crate::common::*;
+use serde_json::Value;
+
+fn parse_json(output: &std::process::Output) -> Value {
+    assert!(output.status.success(), "Command failed: {}", String::from_utf8_lossy(&output.stderr));
+    serde_json::from_slice(&output.stdout)
+        .unwrap_or_else(|e| panic!("Output is not JSON ({}): {}", e, String::from_utf8_lossy(&output.stdout)))
+}
+
+#[test]
+fn test_snapshot_json_summary() {
+    let env = TestEnvironment::new();
+    create_test_files(&env.test_data_dir).unwrap();
+
+    let output = run_backuptool(&[
+        "snapshot",
+        "--target-directory", env.test_data_dir.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap(),
+        "--json"
+    ]);
+
+    let summary = parse_json(&output);
+    assert_eq!(summary["snapshot_id"], 1);
+    assert_eq!(summary["files_processed"], 3);
+    assert_eq!(summary["total_size"], 34);
+}
+
+#[test]
+fn test_list_json() {
+    let env = TestEnvironment::new();
+    create_test_files(&env.test_data_dir).unwrap();
+
+    for _ in 0..2 {
+        run_backuptool(&[
+            "snapshot",
+            "--target-directory", env.test_data_dir.to_str().unwrap(),
+            "--database", env.db_path.to_str().unwrap()
+        ]);
+    }
+
+    let output = run_backuptool(&[
+        "--json",
+        "list",
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+
+    let snapshots = parse_json(&output);
+    let snapshots = snapshots.as_array().unwrap();
+    assert_eq!(snapshots.len(), 2);
+    assert_eq!(snapshots[0]["id"], 1);
+    assert_eq!(snapshots[1]["id"], 2);
+    assert_eq!(snapshots[0]["total_size"], 34);
+    assert!(snapshots[0]["timestamp"].as_str().unwrap().contains('T'));
+}
+
+#[test]
+fn test_restore_and_prune_json() {
+    let env = TestEnvironment::new();
+    create_test_files(&env.test_data_dir).unwrap();
+
+    run_backuptool(&[
+        "snapshot",
+        "--target-directory", env.test_data_dir.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+
+    let restore_dir = env.restore_dir("json");
+    let output = run_backuptool(&[
+        "restore",
+        "--snapshot-number", "1",
+        "--output-directory", restore_dir.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap(),
+        "--json"
+    ]);
+    let report = parse_json(&output);
+    assert_eq!(report["snapshot_id"], 1);
+    assert_eq!(report["files_restored"], 3);
+
+    let output = run_backuptool(&[
+        "prune",
+        "--snapshot", "1",
+        "--database", env.db_path.to_str().unwrap(),
+        "--json"
+    ]);
+    let report = parse_json(&output);
+    assert_eq!(report["snapshot_id"], 1);
+}
@@ -0,0 +1,96 @@
+use crate::common::*;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Plants a lock file as another backuptool process would, with a heartbeat `age` old.
+fn plant_lock(env: &TestEnvironment, kind: &str, host: &str, age: Duration) -> PathBuf {
+    let dir = PathBuf::from(format!("{}.locks", env.db_path.display()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(format!("{}-4242-{}.lock", host, kind));
+    let contents = format!(
+        r#"{{"kind":"{}","pid":4242,"host":"{}","acquired_at":"2026-10-01T12:00:00Z"}}"#,
+        kind, host
+    );
+    fs::write(&path, contents).unwrap();
+    let file = fs::File::options().write(true).open(&path).unwrap();
+    file.set_modified(SystemTime::now() - age).unwrap();
+    path
+}
+
+fn snapshot(env: &TestEnvironment, extra: &[&str]) -> std::process::Output {
+    let mut args = extra.to_vec();
+    args.extend([
+        "snapshot",
+        "--target-directory", env.test_data_dir.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+    run_backuptool(&args)
+}
+
+#[test]
+fn test_exclusive_lock_blocks_commands_until_unlocked() {
+    let env = TestEnvironment::new();
+    create_test_files(&env.test_data_dir).unwrap();
+    let lock = plant_lock(&env, "exclusive", "other-host", Duration::ZERO);
+
+    let output = snapshot(&env, &[]);
+    assert_eq!(output.status.code(), Some(6));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("exclusive lock held by pid 4242 on other-host"), "stderr: {}", stderr);
+
+    // Waiting gives up once the timeout passes
+    let started = Instant::now();
+    let output = snapshot(&env, &["--lock-wait", "1"]);
+    assert_eq!(output.status.code(), Some(6));
+    assert!(started.elapsed() >= Duration::from_secs(1));
+
+    // A plain unlock only removes stale locks
+    let output = run_backuptool(&["unlock", "--database", env.db_path.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Kept active exclusive lock"));
+    assert!(lock.exists());
+
+    let output = run_backuptool(&["unlock", "--all", "--database", env.db_path.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Removed exclusive lock held by pid 4242"));
+    assert!(!lock.exists());
+
+    let output = snapshot(&env, &[]);
+    assert!(output.status.success(), "Snapshot failed: {}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn test_shared_lock_blocks_only_prune() {
+    let env = TestEnvironment::new();
+    create_test_files(&env.test_data_dir).unwrap();
+    plant_lock(&env, "shared", "other-host", Duration::ZERO);
+
+    let output = snapshot(&env, &[]);
+    assert!(output.status.success(), "Snapshot failed: {}", String::from_utf8_lossy(&output.stderr));
+
+    let output = run_backuptool(&["prune", "--snapshot", "1", "--now", "--database", env.db_path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(6));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("shared lock held by pid 4242"));
+}
+
+#[test]
+fn test_stale_locks_are_ignored_and_cleaned_up() {
+    let env = TestEnvironment::new();
+    create_test_files(&env.test_data_dir).unwrap();
+
+    // No heartbeat for an hour
+    let abandoned = plant_lock(&env, "exclusive", "other-host", Duration::from_secs(3600));
+    let output = snapshot(&env, &[]);
+    assert!(output.status.success(), "Snapshot failed: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(!abandoned.exists());
+
+    let abandoned = plant_lock(&env, "shared", "other-host", Duration::from_secs(3600));
+    let output = run_backuptool(&["--json", "unlock", "--database", env.db_path.to_str().unwrap()]);
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["removed"][0]["host"], "other-host");
+    assert_eq!(report["removed"][0]["stale"], true);
+    assert_eq!(report["remaining"], serde_json::json!([]));
+    assert!(!abandoned.exists());
+}
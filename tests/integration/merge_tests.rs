@@ -0,0 +1,109 @@
+use crate::common::*;
+use std::fs;
+use std::path::Path;
+
+fn snapshot(dir: &Path, db: &str) {
+    let output = run_backuptool(&["snapshot", "--target-directory", dir.to_str().unwrap(), "--database", db]);
+    assert!(output.status.success(), "Snapshot failed: {}", String::from_utf8_lossy(&output.stderr));
+}
+
+fn run_json(args: &[&str]) -> serde_json::Value {
+    let mut full = args.to_vec();
+    full.push("--json");
+    let output = run_backuptool(&full);
+    assert!(output.status.success(), "{:?} failed: {}", args, String::from_utf8_lossy(&output.stderr));
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[test]
+fn test_merge_renumbers_by_time_and_deduplicates() {
+    let env = TestEnvironment::new();
+    create_test_files(&env.test_data_dir).unwrap();
+    let team_a = env.db_path.to_str().unwrap();
+    let team_b_path = env.temp_dir.path().join("team_b.db");
+    let team_b = team_b_path.to_str().unwrap();
+    init_repository(&team_b_path);
+    let other_dir = env.temp_dir.path().join("other_data");
+    fs::create_dir_all(&other_dir).unwrap();
+    fs::write(other_dir.join("shared.txt"), "Hello World").unwrap();
+    fs::write(other_dir.join("own.txt"), "Team B only").unwrap();
+
+    snapshot(&env.test_data_dir, team_a);
+    snapshot(&other_dir, team_b);
+    fs::write(env.test_data_dir.join("file2.txt"), "Changed").unwrap();
+    snapshot(&env.test_data_dir, team_a);
+    // Team B also holds a copy of team A's first snapshot
+    run_json(&["copy", "--from", team_a, "--to", team_b, "--snapshot", "1"]);
+
+    let merged_path = env.temp_dir.path().join("merged.db");
+    let merged = merged_path.to_str().unwrap();
+    let report = run_json(&["merge", team_a, team_b, "--output", merged]);
+    let remapped: Vec<(String, u64, u64)> = report["snapshots"].as_array().unwrap().iter()
+        .map(|s| (s["repository"].as_str().unwrap().to_string(), s["source_id"].as_u64().unwrap(), s["snapshot_id"].as_u64().unwrap()))
+        .collect();
+    assert_eq!(remapped, vec![
+        (team_a.to_string(), 1, 1),
+        (team_b.to_string(), 1, 2),
+        (team_a.to_string(), 2, 3),
+    ], "Snapshots are numbered in the order they were taken");
+    assert_eq!(report["duplicates"][0]["source_id"], 2);
+    assert_eq!(report["duplicates"][0]["snapshot_id"], 1);
+    assert_eq!(report["check"]["problems"], serde_json::json!([]));
+
+    let conn = rusqlite::Connection::open(&merged_path).unwrap();
+    let blocks: i64 = conn.query_row("SELECT COUNT(*) FROM content_blocks", [], |row| row.get(0)).unwrap();
+    assert_eq!(blocks, 5, "Content shared between the teams is stored once");
+
+    let restore_dir = env.restore_dir("merged");
+    let output = run_backuptool(&[
+        "restore",
+        "--snapshot-number", "2",
+        "--output-directory", restore_dir.to_str().unwrap(),
+        "--database", merged
+    ]);
+    assert!(output.status.success());
+    verify_file_content(&restore_dir.join("shared.txt"), "Hello World");
+    verify_file_content(&restore_dir.join("own.txt"), "Team B only");
+
+    let output = run_backuptool(&["merge", team_a, team_b, "--output", merged]);
+    assert_eq!(output.status.code(), Some(2), "merge never writes into an existing repository");
+}
+
+#[test]
+fn test_split_and_check() {
+    let env = TestEnvironment::new();
+    create_test_files(&env.test_data_dir).unwrap();
+    let db = env.db_path.to_str().unwrap();
+    let project = env.temp_dir.path().join("project");
+    fs::create_dir_all(&project).unwrap();
+    fs::write(project.join("main.rs"), "fn main() {}").unwrap();
+    snapshot(&env.test_data_dir, db);
+    snapshot(&project, db);
+    snapshot(&project, db);
+
+    let split_path = env.temp_dir.path().join("project.db");
+    let split = split_path.to_str().unwrap();
+    let report = run_json(&["split", "--target-directory", project.to_str().unwrap(), "--output", split, "--database", db]);
+    assert_eq!(report["snapshots"].as_array().unwrap().len(), 2);
+    assert_eq!(report["blocks_copied"], 1);
+    let listed = run_json(&["list", "--database", split]);
+    assert_eq!(listed.as_array().unwrap().len(), 2);
+
+    let report = run_json(&["check", "--database", split]);
+    assert_eq!(report["blocks"], 1);
+    assert_eq!(report["problems"], serde_json::json!([]));
+
+    let missing = env.temp_dir.path().join("missing.db");
+    let output = run_backuptool(&[
+        "split", "--target-directory", "/nowhere", "--output", missing.to_str().unwrap(), "--database", db
+    ]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(!missing.exists(), "A failed split leaves no repository behind");
+
+    let conn = rusqlite::Connection::open(&env.db_path).unwrap();
+    conn.execute("UPDATE packs SET data = zeroblob(length(data))", []).unwrap();
+    drop(conn);
+    let output = run_backuptool(&["check", "--database", db]);
+    assert_eq!(output.status.code(), Some(5));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("does not match its hash"));
+}
@@ -1,7 +1,21 @@
 mod common;
+mod s3_stub;
 mod snapshot_tests;
 mod list_tests;
 mod restore_tests;
 mod prune_tests;
 mod sanity_tests;
-mod edge_case_tests;
\ No newline at end of file
+mod edge_case_tests;
+mod remote_tests;
+mod json_tests;
+mod tag_tests;
+mod selector_tests;
+mod stdin_tests;
+mod export_tests;
+mod import_tests;
+mod lock_tests;
+mod config_tests;
+mod init_tests;
+mod copy_tests;
+mod merge_tests;
+mod stats_tests;
\ No newline at end of file
@@ -154,10 +154,318 @@ fn test_prune_frees_space() {
     run_backuptool(&[
         "prune",
         "--snapshot", "1",
+        "--now",
         "--database", env.db_path.to_str().unwrap()
     ]);
     
     let db_size_after = fs::metadata(&env.db_path).unwrap().len();
     
     assert!(db_size_after <= db_size_before, "Database size should not increase after pruning");
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_prune_repacks_mostly_unreferenced_packs() {
+    let env = TestEnvironment::new();
+
+    fs::write(env.test_data_dir.join("kept.txt"), "Kept small file").unwrap();
+    fs::write(env.test_data_dir.join("dropped1.txt"), "x".repeat(2000)).unwrap();
+    fs::write(env.test_data_dir.join("dropped2.txt"), "y".repeat(2000)).unwrap();
+
+    run_backuptool(&[
+        "snapshot",
+        "--target-directory", env.test_data_dir.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+
+    fs::remove_file(env.test_data_dir.join("dropped1.txt")).unwrap();
+    fs::remove_file(env.test_data_dir.join("dropped2.txt")).unwrap();
+
+    run_backuptool(&[
+        "snapshot",
+        "--target-directory", env.test_data_dir.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+
+    let output = run_backuptool(&[
+        "prune",
+        "--snapshot", "1",
+        "--now",
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Packs repacked: 1"), "Mostly-garbage pack should be rewritten: {}", stdout);
+
+    let restore_dir = env.restore_dir("after_repack");
+    let output = run_backuptool(&[
+        "restore",
+        "--snapshot-number", "2",
+        "--output-directory", restore_dir.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+    assert!(output.status.success());
+    verify_file_content(&restore_dir.join("kept.txt"), "Kept small file");
+    verify_file_not_exists(&restore_dir.join("dropped1.txt"));
+}
+
+#[test]
+fn test_prune_refuses_protected_snapshot() {
+    let env = TestEnvironment::new();
+    create_test_files(&env.test_data_dir).unwrap();
+
+    for _ in 0..3 {
+        run_backuptool(&[
+            "snapshot",
+            "--target-directory", env.test_data_dir.to_str().unwrap(),
+            "--database", env.db_path.to_str().unwrap()
+        ]);
+    }
+
+    let output = run_backuptool(&["protect", "--snapshot", "1", "--database", env.db_path.to_str().unwrap()]);
+    assert!(output.status.success());
+
+    let output = run_backuptool(&["prune", "--snapshot", "1", "--database", env.db_path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(11), "Protected snapshot must not be pruned");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Snapshot 1 is protected"));
+
+    // Retention keeps the protected snapshot without spending a slot on it
+    let output = run_backuptool(&["prune", "--keep-last", "1", "--database", env.db_path.to_str().unwrap()]);
+    assert!(output.status.success());
+
+    let output = run_backuptool(&["list", "--json", "--database", env.db_path.to_str().unwrap()]);
+    let snapshots: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let ids: Vec<u64> = snapshots.as_array().unwrap().iter().map(|s| s["id"].as_u64().unwrap()).collect();
+    assert_eq!(ids, vec![1, 3]);
+    assert_eq!(snapshots[0]["protected"], true);
+
+    let output = run_backuptool(&["unprotect", "--snapshot", "1", "--database", env.db_path.to_str().unwrap()]);
+    assert!(output.status.success());
+    let output = run_backuptool(&["prune", "--snapshot", "1", "--database", env.db_path.to_str().unwrap()]);
+    assert!(output.status.success());
+}
+
+#[test]
+fn test_prune_forgets_until_gc() {
+    let env = TestEnvironment::new();
+    let db = env.db_path.to_str().unwrap();
+
+    fs::write(env.test_data_dir.join("unique1.txt"), "Unique to snapshot 1").unwrap();
+    run_backuptool(&["snapshot", "--target-directory", env.test_data_dir.to_str().unwrap(), "--database", db]);
+
+    let output = run_backuptool(&["prune", "--snapshot", "1", "--json", "--database", db]);
+    assert!(output.status.success(), "Prune failed: {}", String::from_utf8_lossy(&output.stderr));
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["forgotten"], true);
+    assert_eq!(report["bytes_reclaimed"], 0);
+
+    let output = run_backuptool(&["undelete", "--snapshot", "1", "--database", db]);
+    assert!(output.status.success(), "Undelete failed: {}", String::from_utf8_lossy(&output.stderr));
+    let restore_dir = env.restore_dir("undeleted");
+    let output = run_backuptool(&[
+        "restore", "--snapshot-number", "1", "--output-directory", restore_dir.to_str().unwrap(), "--database", db
+    ]);
+    assert!(output.status.success());
+    verify_file_content(&restore_dir.join("unique1.txt"), "Unique to snapshot 1");
+
+    run_backuptool(&["prune", "--snapshot", "1", "--database", db]);
+    let output = run_backuptool(&["gc", "--grace-days", "0", "--json", "--database", db]);
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["snapshots_removed"], serde_json::json!([1]));
+}
+
+#[test]
+fn test_forget_undelete_and_gc() {
+    let env = TestEnvironment::new();
+    let db = env.db_path.to_str().unwrap();
+
+    fs::write(env.test_data_dir.join("shared.txt"), "Shared content").unwrap();
+    fs::write(env.test_data_dir.join("unique1.txt"), "Unique to snapshot 1").unwrap();
+    run_backuptool(&["snapshot", "--target-directory", env.test_data_dir.to_str().unwrap(), "--database", db]);
+    fs::remove_file(env.test_data_dir.join("unique1.txt")).unwrap();
+    run_backuptool(&["snapshot", "--target-directory", env.test_data_dir.to_str().unwrap(), "--database", db]);
+
+    let output = run_backuptool(&["forget", "--snapshot", "1", "--database", db]);
+    assert!(output.status.success(), "Forget failed: {}", String::from_utf8_lossy(&output.stderr));
+
+    // A forgotten snapshot is hidden from every command but can be listed and brought back
+    let output = run_backuptool(&["list", "--json", "--database", db]);
+    let snapshots: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(snapshots.as_array().unwrap().len(), 1);
+    let output = run_backuptool(&["list", "--deleted", "--json", "--database", db]);
+    let forgotten: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(forgotten[0]["id"], 1);
+    let restore_dir = env.restore_dir("forgotten");
+    let output = run_backuptool(&[
+        "restore", "--snapshot-number", "1", "--output-directory", restore_dir.to_str().unwrap(), "--database", db
+    ]);
+    assert_eq!(output.status.code(), Some(3));
+
+    let output = run_backuptool(&["undelete", "--snapshot", "1", "--database", db]);
+    assert!(output.status.success(), "Undelete failed: {}", String::from_utf8_lossy(&output.stderr));
+    let output = run_backuptool(&[
+        "restore", "--snapshot-number", "1", "--output-directory", restore_dir.to_str().unwrap(), "--database", db
+    ]);
+    assert!(output.status.success());
+    verify_file_content(&restore_dir.join("unique1.txt"), "Unique to snapshot 1");
+
+    // Within the grace period gc keeps the forgotten snapshot and its data
+    run_backuptool(&["forget", "--snapshot", "1", "--database", db]);
+    let output = run_backuptool(&["gc", "--json", "--database", db]);
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["snapshots_removed"], serde_json::json!([]));
+    assert_eq!(report["bytes_reclaimed"], 0);
+
+    let output = run_backuptool(&["gc", "--grace-days", "0", "--json", "--database", db]);
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["snapshots_removed"], serde_json::json!([1]));
+    assert_eq!(report["bytes_reclaimed"], "Unique to snapshot 1".len());
+
+    let output = run_backuptool(&["undelete", "--snapshot", "1", "--database", db]);
+    assert_eq!(output.status.code(), Some(3));
+
+    let restore_dir = env.restore_dir("after_gc");
+    let output = run_backuptool(&[
+        "restore", "--snapshot-number", "2", "--output-directory", restore_dir.to_str().unwrap(), "--database", db
+    ]);
+    assert!(output.status.success());
+    verify_file_content(&restore_dir.join("shared.txt"), "Shared content");
+}
+
+#[test]
+fn test_forget_refuses_protected_snapshot() {
+    let env = TestEnvironment::new();
+    create_test_files(&env.test_data_dir).unwrap();
+    let db = env.db_path.to_str().unwrap();
+
+    run_backuptool(&["snapshot", "--target-directory", env.test_data_dir.to_str().unwrap(), "--database", db]);
+    run_backuptool(&["snapshot", "--target-directory", env.test_data_dir.to_str().unwrap(), "--database", db]);
+    run_backuptool(&["protect", "--snapshot", "2", "--database", db]);
+
+    // Nothing is forgotten when any of the snapshots is protected
+    let output = run_backuptool(&["forget", "--snapshot", "1", "--snapshot", "2", "--database", db]);
+    assert_eq!(output.status.code(), Some(11));
+    let output = run_backuptool(&["list", "--deleted", "--json", "--database", db]);
+    assert_eq!(serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap(), serde_json::json!([]));
+}
+
+fn snapshot_then_drop_large_file(env: &TestEnvironment) {
+    let db = env.db_path.to_str().unwrap();
+    fs::write(env.test_data_dir.join("small.txt"), "Small file").unwrap();
+    fs::write(env.test_data_dir.join("large.bin"), pseudo_random(2 * 1024 * 1024, 7)).unwrap();
+    run_backuptool(&["snapshot", "--target-directory", env.test_data_dir.to_str().unwrap(), "--database", db]);
+    fs::remove_file(env.test_data_dir.join("large.bin")).unwrap();
+    run_backuptool(&["snapshot", "--target-directory", env.test_data_dir.to_str().unwrap(), "--database", db]);
+}
+
+#[test]
+fn test_prune_shrinks_database_file() {
+    let env = TestEnvironment::new();
+    snapshot_then_drop_large_file(&env);
+    let size_before = fs::metadata(&env.db_path).unwrap().len();
+
+    let output = run_backuptool(&["prune", "--snapshot", "1", "--now", "--json", "--database", env.db_path.to_str().unwrap()]);
+    assert!(output.status.success(), "Prune failed: {}", String::from_utf8_lossy(&output.stderr));
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    let size_after = fs::metadata(&env.db_path).unwrap().len();
+    assert_eq!(report["database_size"]["size_before"], size_before);
+    assert_eq!(report["database_size"]["size_after"], size_after);
+    assert!(size_after + 1024 * 1024 < size_before, "{} -> {}", size_before, size_after);
+}
+
+#[test]
+fn test_compact_shrinks_legacy_database() {
+    let env = TestEnvironment::new();
+    snapshot_then_drop_large_file(&env);
+
+    // Databases created before incremental auto-vacuum never give pages back on their own
+    let conn = rusqlite::Connection::open(&env.db_path).unwrap();
+    conn.execute_batch("PRAGMA auto_vacuum = NONE; VACUUM;").unwrap();
+    drop(conn);
+
+    let output = run_backuptool(&["prune", "--snapshot", "1", "--now", "--database", env.db_path.to_str().unwrap()]);
+    assert!(output.status.success());
+    let size_before = fs::metadata(&env.db_path).unwrap().len();
+    assert!(size_before > 2 * 1024 * 1024);
+
+    let output = run_backuptool(&["compact", "--database", env.db_path.to_str().unwrap()]);
+    assert!(output.status.success(), "Compact failed: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains(&format!("Database size: {} -> ", size_before)));
+    let size_after = fs::metadata(&env.db_path).unwrap().len();
+    assert!(size_after + 1024 * 1024 < size_before, "{} -> {}", size_before, size_after);
+
+    let conn = rusqlite::Connection::open(&env.db_path).unwrap();
+    let auto_vacuum: i64 = conn.query_row("PRAGMA auto_vacuum", [], |row| row.get(0)).unwrap();
+    assert_eq!(auto_vacuum, 2, "compact switches the database to incremental auto-vacuum");
+
+    let restore_dir = env.restore_dir("after_compact");
+    let output = run_backuptool(&[
+        "restore", "--snapshot-number", "2",
+        "--output-directory", restore_dir.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+    assert!(output.status.success());
+    verify_file_content(&restore_dir.join("small.txt"), "Small file");
+}
+
+#[test]
+fn test_prune_lists_ranges_and_age() {
+    let env = TestEnvironment::new();
+    let db = env.db_path.to_str().unwrap();
+    let other_dir = env.temp_dir.path().join("other");
+    fs::create_dir_all(&other_dir).unwrap();
+    fs::write(other_dir.join("other.txt"), "Other source").unwrap();
+
+    for i in 1..=6 {
+        fs::write(env.test_data_dir.join("file.txt"), format!("Version {}", i)).unwrap();
+        run_backuptool(&["snapshot", "--target-directory", env.test_data_dir.to_str().unwrap(), "--database", db]);
+    }
+    run_backuptool(&["snapshot", "--target-directory", other_dir.to_str().unwrap(), "--database", db]);
+
+    let ids = |output: &std::process::Output, field: &str| -> Vec<u64> {
+        let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        report[field].as_array().unwrap().iter().map(|id| id.as_u64().unwrap()).collect()
+    };
+
+    let output = run_backuptool(&["prune", "--snapshot", "1,3-4", "--json", "--database", db]);
+    assert!(output.status.success(), "Prune failed: {}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(ids(&output, "removed"), vec![1, 3, 4]);
+
+    // Age is scoped to one source directory, so the old snapshot of the other one survives
+    let conn = rusqlite::Connection::open(&env.db_path).unwrap();
+    conn.execute("UPDATE snapshots SET timestamp = '2020-01-01T00:00:00+00:00' WHERE id IN (2, 7)", []).unwrap();
+    drop(conn);
+    let output = run_backuptool(&[
+        "prune", "--older-than", "90d",
+        "--target-directory", env.test_data_dir.to_str().unwrap(),
+        "--json", "--database", db
+    ]);
+    assert!(output.status.success(), "Prune failed: {}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(ids(&output, "removed"), vec![2]);
+
+    // Protected snapshots in a range are kept rather than failing the whole run
+    run_backuptool(&["protect", "--snapshot", "5", "--database", db]);
+    let output = run_backuptool(&["prune", "--snapshot", "5-6", "--json", "--database", db]);
+    assert!(output.status.success(), "Prune failed: {}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(ids(&output, "removed"), vec![6]);
+    assert_eq!(ids(&output, "kept"), vec![5]);
+
+    let output = run_backuptool(&[
+        "prune", "--snapshot", "7", "--target-directory", env.test_data_dir.to_str().unwrap(), "--database", db
+    ]);
+    assert_eq!(output.status.code(), Some(2));
+    let output = run_backuptool(&["prune", "--snapshot", "9-8", "--database", db]);
+    assert!(!output.status.success());
+    // Malformed, zero and negative ages are usage errors and select nothing
+    for age in ["--older-than=5é", "--older-than=-1d", "--older-than=0h", "--older-than=é"] {
+        let output = run_backuptool(&["prune", age, "--database", db]);
+        assert_eq!(output.status.code(), Some(2), "{}: {}", age, String::from_utf8_lossy(&output.stderr));
+    }
+
+    let output = run_backuptool(&["list", "--json", "--database", db]);
+    let snapshots: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let remaining: Vec<u64> = snapshots.as_array().unwrap().iter().map(|s| s["id"].as_u64().unwrap()).collect();
+    assert_eq!(remaining, vec![5, 7]);
+}
@@ -0,0 +1,223 @@
+use crate::common::*;
+use crate::s3_stub::S3Stub;
+use std::fs;
+
+const CREDENTIALS: &[(&str, &str)] = &[
+    ("AWS_ACCESS_KEY_ID", "test-access-key"),
+    ("AWS_SECRET_ACCESS_KEY", "test-secret-key"),
+    ("AWS_REGION", "us-east-1"),
+];
+
+fn run_remote(command: &str, stub: &S3Stub, db_path: &std::path::Path) -> std::process::Output {
+    run_backuptool_with_env(&[
+        command,
+        "--remote", "s3://backups/offsite",
+        "--endpoint", &stub.endpoint,
+        "--database", db_path.to_str().unwrap()
+    ], CREDENTIALS)
+}
+
+#[test]
+fn test_push_and_pull_roundtrip() {
+    let env = TestEnvironment::new();
+    let stub = S3Stub::start();
+    create_test_files(&env.test_data_dir).unwrap();
+
+    run_backuptool(&[
+        "snapshot",
+        "--target-directory", env.test_data_dir.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+
+    let output = run_remote("push", &stub, &env.db_path);
+    assert!(output.status.success(), "Push failed: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(!stub.saw_unsigned_request(), "All requests should carry a SigV4 signature");
+    assert!(stub.object_keys().contains(&"/backups/offsite/index/snapshots.json".to_string()));
+
+    let pulled_db = env.temp_dir.path().join("pulled.db");
+    init_repository(&pulled_db);
+    let output = run_remote("pull", &stub, &pulled_db);
+    assert!(output.status.success(), "Pull failed: {}", String::from_utf8_lossy(&output.stderr));
+
+    let restore_dir = env.restore_dir("pulled");
+    let output = run_backuptool(&[
+        "restore",
+        "--snapshot-number", "1",
+        "--output-directory", restore_dir.to_str().unwrap(),
+        "--database", pulled_db.to_str().unwrap()
+    ]);
+    assert!(output.status.success());
+    verify_file_content(&restore_dir.join("file1.txt"), "Hello World");
+    verify_file_content(&restore_dir.join("subdir/file3.txt"), "Nested file");
+}
+
+#[test]
+fn test_push_uploads_only_new_content() {
+    let env = TestEnvironment::new();
+    let stub = S3Stub::start();
+    create_test_files(&env.test_data_dir).unwrap();
+
+    run_backuptool(&[
+        "snapshot",
+        "--target-directory", env.test_data_dir.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+    assert!(run_remote("push", &stub, &env.db_path).status.success());
+    let puts_after_first = stub.put_count();
+
+    fs::write(env.test_data_dir.join("new.txt"), "Brand new content").unwrap();
+    run_backuptool(&[
+        "snapshot",
+        "--target-directory", env.test_data_dir.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+    assert!(run_remote("push", &stub, &env.db_path).status.success());
+
+    // One new content block plus the rewritten index
+    assert_eq!(stub.put_count() - puts_after_first, 2);
+}
+
+#[test]
+fn test_pull_skips_existing_snapshots() {
+    let env = TestEnvironment::new();
+    let stub = S3Stub::start();
+    create_test_files(&env.test_data_dir).unwrap();
+
+    run_backuptool(&[
+        "snapshot",
+        "--target-directory", env.test_data_dir.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+    assert!(run_remote("push", &stub, &env.db_path).status.success());
+
+    let output = run_remote("pull", &stub, &env.db_path);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Snapshots pulled: 0"));
+}
+
+#[test]
+fn test_push_retries_transient_errors() {
+    let env = TestEnvironment::new();
+    let stub = S3Stub::start();
+    create_test_files(&env.test_data_dir).unwrap();
+
+    run_backuptool(&[
+        "snapshot",
+        "--target-directory", env.test_data_dir.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+
+    stub.fail_next(2);
+    let output = run_remote("push", &stub, &env.db_path);
+    assert!(output.status.success(), "Push should survive transient errors: {}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn test_push_requires_credentials() {
+    let env = TestEnvironment::new();
+    let stub = S3Stub::start();
+
+    let output = backuptool_command(env.temp_dir.path())
+        .args([
+            "push",
+            "--remote", "s3://backups/offsite",
+            "--endpoint", &stub.endpoint,
+            "--database", env.db_path.to_str().unwrap()
+        ])
+        .env_remove("AWS_ACCESS_KEY_ID")
+        .env_remove("AWS_SECRET_ACCESS_KEY")
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success(), "Push without credentials should fail");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("AWS_ACCESS_KEY_ID"));
+}
+
+#[test]
+fn test_push_and_pull_chunked_content() {
+    let env = TestEnvironment::new();
+    let stub = S3Stub::start();
+    let dump = pseudo_random(6 * 1024 * 1024, 3);
+
+    let output = run_backuptool_with_stdin(&[
+        "snapshot", "--stdin", "--stdin-filename", "db.sql",
+        "--database", env.db_path.to_str().unwrap()
+    ], &dump);
+    assert!(output.status.success());
+
+    let output = run_backuptool_with_env(&[
+        "push", "--json",
+        "--remote", "s3://backups/offsite",
+        "--endpoint", &stub.endpoint,
+        "--database", env.db_path.to_str().unwrap()
+    ], CREDENTIALS);
+    assert!(output.status.success(), "Push failed: {}", String::from_utf8_lossy(&output.stderr));
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["bytes_uploaded"], dump.len(), "Chunked content should be uploaded once, as its chunks");
+
+    let pulled_db = env.temp_dir.path().join("pulled.db");
+    init_repository(&pulled_db);
+    let output = run_remote("pull", &stub, &pulled_db);
+    assert!(output.status.success(), "Pull failed: {}", String::from_utf8_lossy(&output.stderr));
+
+    let output = run_backuptool(&["cat", "--snapshot", "1", "db.sql", "--database", pulled_db.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert!(output.stdout == dump, "Pulled content differs from the original");
+}
+
+#[test]
+fn test_push_keeps_snapshots_only_the_remote_has() {
+    let env = TestEnvironment::new();
+    let stub = S3Stub::start();
+    create_test_files(&env.test_data_dir).unwrap();
+    let other_db = env.temp_dir.path().join("other_host.db");
+    init_repository(&other_db);
+
+    for db in [&env.db_path, &other_db] {
+        run_backuptool(&[
+            "snapshot",
+            "--target-directory", env.test_data_dir.to_str().unwrap(),
+            "--database", db.to_str().unwrap()
+        ]);
+        assert!(run_remote("push", &stub, db).status.success());
+    }
+    let output = run_backuptool(&["prune", "--snapshot", "1", "--database", env.db_path.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert!(run_remote("push", &stub, &env.db_path).status.success());
+
+    let pulled_db = env.temp_dir.path().join("pulled.db");
+    init_repository(&pulled_db);
+    let output = run_remote("pull", &stub, &pulled_db);
+    assert!(output.status.success(), "Pull failed: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Snapshots pulled: 2"));
+}
+
+#[test]
+fn test_pull_refuses_paths_outside_the_snapshot() {
+    let env = TestEnvironment::new();
+    let stub = S3Stub::start();
+    create_test_files(&env.test_data_dir).unwrap();
+    run_backuptool(&[
+        "snapshot",
+        "--target-directory", env.test_data_dir.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+    assert!(run_remote("push", &stub, &env.db_path).status.success());
+
+    // A tampered index must not be able to place files outside the restore directory
+    let key = "/backups/offsite/index/snapshots.json";
+    let mut index: serde_json::Value = serde_json::from_slice(&stub.object(key).unwrap()).unwrap();
+    index["snapshots"][0]["files"][0]["path"] = serde_json::json!("../escape");
+    stub.set_object(key, serde_json::to_vec(&index).unwrap());
+
+    let pulled_db = env.temp_dir.path().join("pulled.db");
+    init_repository(&pulled_db);
+    let output = run_remote("pull", &stub, &pulled_db);
+    assert_eq!(output.status.code(), Some(5), "Pull should refuse the index: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("../escape"));
+
+    let output = run_backuptool(&["list", "--json", "--database", pulled_db.to_str().unwrap()]);
+    let snapshots: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(snapshots.as_array().unwrap().is_empty(), "No snapshot should be pulled: {}", snapshots);
+}
@@ -177,4 +177,112 @@ fn test_restore_files_with_special_characters() {
     verify_file_exists(&restore_dir.join("file with spaces.txt"));
     verify_file_exists(&restore_dir.join("file-with-dashes.txt"));
     verify_file_exists(&restore_dir.join("file_with_underscores.txt"));
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_restore_nonexistent_snapshot_exit_code() {
+    let env = TestEnvironment::new();
+
+    let restore_dir = env.restore_dir("nonexistent_code");
+    let output = run_backuptool(&[
+        "restore",
+        "--snapshot-number", "999",
+        "--output-directory", restore_dir.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+
+    assert_eq!(output.status.code(), Some(3), "Missing snapshot should exit with code 3");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Snapshot 999 does not exist"));
+}
+
+#[test]
+fn test_restore_continue_on_error_and_retry_failed() {
+    let env = TestEnvironment::new();
+    create_test_files(&env.test_data_dir).unwrap();
+
+    run_backuptool(&[
+        "snapshot",
+        "--target-directory", env.test_data_dir.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+
+    // A directory in place of file1.txt makes writing that file fail
+    let restore_dir = env.restore_dir("partial");
+    fs::create_dir_all(restore_dir.join("file1.txt")).unwrap();
+
+    let output = run_backuptool(&[
+        "restore",
+        "--snapshot-number", "1",
+        "--output-directory", restore_dir.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+    assert_eq!(output.status.code(), Some(7), "Write failure should abort with code 7");
+
+    let report_path = env.temp_dir.path().join("restore.json");
+    let output = run_backuptool(&[
+        "restore",
+        "--snapshot-number", "1",
+        "--output-directory", restore_dir.to_str().unwrap(),
+        "--continue-on-error",
+        "--report", report_path.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+    assert_eq!(output.status.code(), Some(10), "Partial restore should exit with code 10");
+    verify_file_content(&restore_dir.join("file2.txt"), "Another file");
+    verify_file_content(&restore_dir.join("subdir/file3.txt"), "Nested file");
+
+    let report: serde_json::Value = serde_json::from_slice(&fs::read(&report_path).unwrap()).unwrap();
+    assert_eq!(report["files_restored"], 2);
+    assert_eq!(report["failed_files"][0]["path"], "file1.txt");
+
+    fs::remove_dir(restore_dir.join("file1.txt")).unwrap();
+    let output = run_backuptool(&[
+        "restore",
+        "--retry-failed", report_path.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+    assert!(output.status.success(), "Retry failed: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Files restored: 1"));
+    verify_file_content(&restore_dir.join("file1.txt"), "Hello World");
+}
+
+#[test]
+fn test_report_written_when_restore_stops_at_first_failure() {
+    let env = TestEnvironment::new();
+    create_test_files(&env.test_data_dir).unwrap();
+
+    run_backuptool(&[
+        "snapshot",
+        "--target-directory", env.test_data_dir.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+
+    let restore_dir = env.restore_dir("stopped");
+    fs::create_dir_all(restore_dir.join("file1.txt")).unwrap();
+    let report_path = env.temp_dir.path().join("restore.json");
+    let output = run_backuptool(&[
+        "restore",
+        "--snapshot-number", "1",
+        "--output-directory", restore_dir.to_str().unwrap(),
+        "--report", report_path.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+    assert_eq!(output.status.code(), Some(7), "Write failure should abort with code 7");
+
+    // The failed file and everything not attempted after it are listed for a retry
+    let report: serde_json::Value = serde_json::from_slice(&fs::read(&report_path).unwrap()).unwrap();
+    let failed = report["failed_files"].as_array().unwrap();
+    assert_eq!(report["files_restored"].as_u64().unwrap() + failed.len() as u64, 3);
+    assert!(failed.iter().any(|f| f["path"] == "file1.txt"));
+
+    fs::remove_dir(restore_dir.join("file1.txt")).unwrap();
+    let output = run_backuptool(&[
+        "restore",
+        "--retry-failed", report_path.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+    assert!(output.status.success(), "Retry failed: {}", String::from_utf8_lossy(&output.stderr));
+    verify_file_content(&restore_dir.join("file1.txt"), "Hello World");
+    verify_file_content(&restore_dir.join("file2.txt"), "Another file");
+    verify_file_content(&restore_dir.join("subdir/file3.txt"), "Nested file");
+}
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// In-process stand-in for an S3 endpoint: path-style PUT/GET/HEAD/DELETE on an in-memory map.
+#[derive(Clone)]
+pub struct S3Stub {
+    pub endpoint: String,
+    state: Arc<Mutex<StubState>>,
+}
+
+#[derive(Default)]
+struct StubState {
+    objects: HashMap<String, Vec<u8>>,
+    puts: usize,
+    failures_remaining: usize,
+    saw_unsigned_request: bool,
+}
+
+impl S3Stub {
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let state = Arc::new(Mutex::new(StubState::default()));
+
+        let server_state = state.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let state = server_state.clone();
+                thread::spawn(move || handle_connection(stream, state));
+            }
+        });
+
+        S3Stub { endpoint, state }
+    }
+
+    /// Makes the next `count` requests fail with 503 Slow Down.
+    pub fn fail_next(&self, count: usize) {
+        self.state.lock().unwrap().failures_remaining = count;
+    }
+
+    pub fn put_count(&self) -> usize {
+        self.state.lock().unwrap().puts
+    }
+
+    pub fn object_keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self.state.lock().unwrap().objects.keys().cloned().collect();
+        keys.sort();
+        keys
+    }
+
+    pub fn object(&self, key: &str) -> Option<Vec<u8>> {
+        self.state.lock().unwrap().objects.get(key).cloned()
+    }
+
+    pub fn set_object(&self, key: &str, data: Vec<u8>) {
+        self.state.lock().unwrap().objects.insert(key.to_string(), data);
+    }
+
+    pub fn saw_unsigned_request(&self) -> bool {
+        self.state.lock().unwrap().saw_unsigned_request
+    }
+}
+
+fn handle_connection(stream: TcpStream, state: Arc<Mutex<StubState>>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut writer = stream;
+
+    loop {
+        let mut request_line = String::new();
+        if reader.read_line(&mut request_line).unwrap_or(0) == 0 {
+            return;
+        }
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().unwrap_or_default().to_string();
+        let path = parts.next().unwrap_or_default().to_string();
+
+        let mut content_length = 0;
+        let mut signed = false;
+        loop {
+            let mut header = String::new();
+            reader.read_line(&mut header).unwrap();
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            let (name, value) = header.split_once(':').unwrap();
+            match name.to_ascii_lowercase().as_str() {
+                "content-length" => content_length = value.trim().parse().unwrap(),
+                "authorization" => signed = value.trim().starts_with("AWS4-HMAC-SHA256 Credential="),
+                _ => {}
+            }
+        }
+
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).unwrap();
+
+        let (status, response_body) = {
+            let mut state = state.lock().unwrap();
+            if !signed {
+                state.saw_unsigned_request = true;
+            }
+            if state.failures_remaining > 0 {
+                state.failures_remaining -= 1;
+                ("503 Slow Down", Vec::new())
+            } else {
+                match method.as_str() {
+                    "PUT" => {
+                        state.puts += 1;
+                        state.objects.insert(path, body);
+                        ("200 OK", Vec::new())
+                    }
+                    "GET" => match state.objects.get(&path) {
+                        Some(data) => ("200 OK", data.clone()),
+                        None => ("404 Not Found", Vec::new()),
+                    },
+                    "HEAD" => match state.objects.contains_key(&path) {
+                        true => ("200 OK", Vec::new()),
+                        false => ("404 Not Found", Vec::new()),
+                    },
+                    "DELETE" => {
+                        state.objects.remove(&path);
+                        ("204 No Content", Vec::new())
+                    }
+                    _ => ("405 Method Not Allowed", Vec::new()),
+                }
+            }
+        };
+
+        let length = if method == "HEAD" { 0 } else { response_body.len() };
+        let header = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\n\r\n", status, length);
+        if writer.write_all(header.as_bytes()).is_err() {
+            return;
+        }
+        if method != "HEAD" && writer.write_all(&response_body).is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use crate::common::*;
+use std::fs;
+
+fn take_snapshot(env: &TestEnvironment, target: &std::path::Path, tag: Option<&str>) {
+    let mut args = vec![
+        "snapshot",
+        "--target-directory", target.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap(),
+    ];
+    if let Some(tag) = tag {
+        args.extend(["--tag", tag]);
+    }
+    let output = run_backuptool(&args);
+    assert!(output.status.success(), "Snapshot failed: {}", String::from_utf8_lossy(&output.stderr));
+}
+
+fn restore_selector(env: &TestEnvironment, selector: &str, suffix: &str) -> std::process::Output {
+    run_backuptool(&[
+        "restore",
+        "--snapshot-number", selector,
+        "--output-directory", env.restore_dir(suffix).to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap()
+    ])
+}
+
+#[test]
+fn test_restore_by_latest_and_offset() {
+    let env = TestEnvironment::new();
+    create_test_files(&env.test_data_dir).unwrap();
+
+    for version in ["one", "two", "three"] {
+        fs::write(env.test_data_dir.join("version.txt"), version).unwrap();
+        take_snapshot(&env, &env.test_data_dir, None);
+    }
+
+    assert!(restore_selector(&env, "latest", "latest").status.success());
+    verify_file_content(&env.restore_dir("latest").join("version.txt"), "three");
+
+    assert!(restore_selector(&env, "latest~2", "oldest").status.success());
+    verify_file_content(&env.restore_dir("oldest").join("version.txt"), "one");
+
+    let output = restore_selector(&env, "latest~3", "none");
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No snapshot matches latest~3"));
+}
+
+#[test]
+fn test_restore_by_path_tag_and_time() {
+    let env = TestEnvironment::new();
+    create_test_files(&env.test_data_dir).unwrap();
+    let other_dir = env.temp_dir.path().join("other");
+    fs::create_dir_all(&other_dir).unwrap();
+    fs::write(other_dir.join("other.txt"), "Other").unwrap();
+
+    take_snapshot(&env, &env.test_data_dir, Some("pre-upgrade"));
+    take_snapshot(&env, &other_dir, None);
+
+    let selector = format!("path:{}:latest", env.test_data_dir.display());
+    assert!(restore_selector(&env, &selector, "path").status.success());
+    verify_file_content(&env.restore_dir("path").join("file1.txt"), "Hello World");
+
+    assert!(restore_selector(&env, "tag:pre-upgrade", "tag").status.success());
+    verify_file_exists(&env.restore_dir("tag").join("subdir/file3.txt"));
+
+    assert!(restore_selector(&env, "@2999-01-01T00:00", "future").status.success());
+    verify_file_content(&env.restore_dir("future").join("other.txt"), "Other");
+
+    assert_eq!(restore_selector(&env, "@2000-01-01", "past").status.code(), Some(3));
+    assert_eq!(restore_selector(&env, "yesterday", "invalid").status.code(), Some(2));
+}
+
+#[test]
+fn test_prune_and_protect_accept_selectors() {
+    let env = TestEnvironment::new();
+    create_test_files(&env.test_data_dir).unwrap();
+
+    take_snapshot(&env, &env.test_data_dir, None);
+    take_snapshot(&env, &env.test_data_dir, None);
+
+    let output = run_backuptool(&["protect", "--snapshot", "latest", "--database", env.db_path.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Snapshot 2 is now protected"));
+
+    let output = run_backuptool(&["prune", "--snapshot", "latest~1", "--database", env.db_path.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Snapshot 1 forgotten"));
+}
+
+#[test]
+fn test_undelete_accepts_selectors_among_forgotten_snapshots() {
+    let env = TestEnvironment::new();
+    create_test_files(&env.test_data_dir).unwrap();
+    let db = env.db_path.to_str().unwrap();
+
+    take_snapshot(&env, &env.test_data_dir, Some("pre-upgrade"));
+    take_snapshot(&env, &env.test_data_dir, None);
+    take_snapshot(&env, &env.test_data_dir, None);
+    let conn = rusqlite::Connection::open(&env.db_path).unwrap();
+    conn.execute("UPDATE snapshots SET uuid = '3f2a1b4c-0000-4000-8000-000000000001' WHERE id = 1", []).unwrap();
+    drop(conn);
+    run_backuptool(&["forget", "--snapshot", "1", "--snapshot", "2", "--database", db]);
+
+    // `latest` is the newest forgotten snapshot, not the newest live one
+    let output = run_backuptool(&["undelete", "--snapshot", "latest", "--database", db]);
+    assert!(output.status.success(), "Undelete failed: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Snapshot 2 restored"));
+
+    let output = run_backuptool(&["undelete", "--snapshot", "3f2a1b", "--database", db]);
+    assert!(output.status.success(), "Undelete failed: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Snapshot 1 restored"));
+
+    let output = run_backuptool(&["undelete", "--snapshot", "tag:pre-upgrade", "--database", db]);
+    assert_eq!(output.status.code(), Some(3), "No forgotten snapshot is left to match");
+}
+
+#[test]
+fn test_select_by_uuid_prefix() {
+    let env = TestEnvironment::new();
+    create_test_files(&env.test_data_dir).unwrap();
+    for version in ["one", "two", "three", "four"] {
+        fs::write(env.test_data_dir.join("version.txt"), version).unwrap();
+        take_snapshot(&env, &env.test_data_dir, None);
+    }
+    let conn = rusqlite::Connection::open(&env.db_path).unwrap();
+    for (id, uuid) in [
+        (1, "3f2a1b4c-0000-4000-8000-000000000001"),
+        (2, "3f2a9d00-0000-4000-8000-000000000002"),
+        (3, "12345678-0000-4000-8000-000000000003"),
+        (4, "00000001-0000-4000-8000-000000000004"),
+    ] {
+        conn.execute("UPDATE snapshots SET uuid = ?1 WHERE id = ?2", rusqlite::params![uuid, id]).unwrap();
+    }
+    drop(conn);
+
+    assert!(restore_selector(&env, "3F2A1B", "prefix").status.success());
+    verify_file_content(&env.restore_dir("prefix").join("version.txt"), "one");
+    assert!(restore_selector(&env, "uuid:1234", "digits").status.success());
+    verify_file_content(&env.restore_dir("digits").join("version.txt"), "three");
+
+    // Every short ID `list` shows selects its own snapshot, even when it is all digits
+    assert!(restore_selector(&env, "12345678", "short_id").status.success());
+    verify_file_content(&env.restore_dir("short_id").join("version.txt"), "three");
+    assert!(restore_selector(&env, "00000001", "leading_zero").status.success());
+    verify_file_content(&env.restore_dir("leading_zero").join("version.txt"), "four");
+
+    let output = restore_selector(&env, "3f2a", "ambiguous");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("matches snapshots 1, 2"));
+    assert_eq!(restore_selector(&env, "ffff", "unknown").status.code(), Some(3));
+
+    let output = run_backuptool(&["list", "--database", env.db_path.to_str().unwrap()]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.lines().any(|line| line.starts_with("2         3f2a9d00  ")), "{}", stdout);
+
+    let output = run_backuptool(&["prune", "--snapshot", "00000001", "--database", env.db_path.to_str().unwrap()]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Snapshot 4 forgotten"));
+
+    // Dashed UUID prefixes are selectors, not ranges
+    for (prefix, snapshot_id) in [("3f2a9d00-0000", 2), ("12345678-0000-4000", 3)] {
+        let output = run_backuptool(&["prune", "--snapshot", prefix, "--database", env.db_path.to_str().unwrap()]);
+        assert!(output.status.success(), "{}: {}", prefix, String::from_utf8_lossy(&output.stderr));
+        assert!(String::from_utf8_lossy(&output.stdout).contains(&format!("Snapshot {} forgotten", snapshot_id)));
+    }
+}
@@ -157,4 +157,149 @@ fn test_snapshot_deeply_nested_structure() {
     ]);
     
     assert!(output.status.success());
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_snapshot_log_progress() {
+    let env = TestEnvironment::new();
+    create_test_files(&env.test_data_dir).unwrap();
+
+    let output = run_backuptool(&[
+        "snapshot",
+        "--target-directory", env.test_data_dir.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap(),
+        "--progress", "log",
+        "--progress-interval", "0"
+    ]);
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Progress: 3/3 files"), "Expected periodic progress lines: {}", stderr);
+    assert!(stderr.contains("Progress: done, 3 files, 34 B"));
+}
+
+#[test]
+fn test_snapshot_without_progress() {
+    let env = TestEnvironment::new();
+    create_test_files(&env.test_data_dir).unwrap();
+
+    let output = run_backuptool(&[
+        "snapshot",
+        "--target-directory", env.test_data_dir.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap(),
+        "--progress", "none"
+    ]);
+
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty(), "No progress output expected");
+
+    // Without --progress, a run whose stderr is not a terminal logs progress lines instead
+    let output = run_backuptool(&[
+        "snapshot",
+        "--target-directory", env.test_data_dir.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap(),
+        "--progress-interval", "0"
+    ]);
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Progress: done"), "Expected logged progress: {}", stderr);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_partial_snapshot_records_errors_and_fails() {
+    let env = TestEnvironment::new();
+    create_test_files(&env.test_data_dir).unwrap();
+    if create_unreadable_file(&env.test_data_dir.join("subdir"), "secret.txt").is_none() {
+        eprintln!("skipping: running as root");
+        return;
+    }
+
+    let output = run_backuptool(&[
+        "snapshot",
+        "--target-directory", env.test_data_dir.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+
+    assert_eq!(output.status.code(), Some(10), "Partial snapshot should exit with code 10");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Warning: Failed to process file subdir/secret.txt:"));
+
+    let output = run_backuptool(&[
+        "list",
+        "--database", env.db_path.to_str().unwrap(),
+        "--json"
+    ]);
+    let snapshots: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(snapshots[0]["error_count"], 1);
+
+    // Skipped paths are stored relative to the snapshot, like its files
+    let conn = rusqlite::Connection::open(&env.db_path).unwrap();
+    let path: String = conn.query_row("SELECT path FROM snapshot_errors", [], |row| row.get(0)).unwrap();
+    assert_eq!(path, "subdir/secret.txt");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_strict_snapshot_aborts_on_first_error() {
+    let env = TestEnvironment::new();
+    create_test_files(&env.test_data_dir).unwrap();
+    if create_unreadable_file(&env.test_data_dir, "secret.txt").is_none() {
+        eprintln!("skipping: running as root");
+        return;
+    }
+
+    let output = run_backuptool(&[
+        "snapshot",
+        "--target-directory", env.test_data_dir.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap(),
+        "--strict"
+    ]);
+
+    assert!(!output.status.success(), "Strict snapshot should fail");
+
+    let output = run_backuptool(&[
+        "list",
+        "--database", env.db_path.to_str().unwrap(),
+        "--json"
+    ]);
+    let snapshots: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(snapshots.as_array().unwrap().is_empty(), "Aborted snapshot should not be stored");
+}
+
+#[test]
+fn test_snapshot_multiple_sources() {
+    let env = TestEnvironment::new();
+    let etc = env.temp_dir.path().join("etc");
+    let srv = env.temp_dir.path().join("srv");
+    let other_srv = env.temp_dir.path().join("other").join("srv");
+    for (dir, content) in [(&etc, "etc config"), (&srv, "srv data"), (&other_srv, "other srv data")] {
+        fs::create_dir_all(dir).unwrap();
+        fs::write(dir.join("app.conf"), content).unwrap();
+    }
+
+    let sources_file = env.temp_dir.path().join("sources.txt");
+    fs::write(&sources_file, format!("# extra roots\n{}\n\n{}\n", srv.display(), other_srv.display())).unwrap();
+
+    let output = run_backuptool(&[
+        "snapshot",
+        "--target-directory", etc.to_str().unwrap(),
+        "--sources-file", sources_file.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+    assert!(output.status.success(), "Snapshot failed: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Files processed: 3"));
+
+    let restore_dir = env.restore_dir("sources");
+    let selector = format!("path:{}", srv.display());
+    let output = run_backuptool(&[
+        "restore",
+        "--snapshot-number", &selector,
+        "--output-directory", restore_dir.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+    assert!(output.status.success(), "Restore failed: {}", String::from_utf8_lossy(&output.stderr));
+
+    verify_file_content(&restore_dir.join("etc/app.conf"), "etc config");
+    verify_file_content(&restore_dir.join("srv/app.conf"), "srv data");
+    verify_file_content(&restore_dir.join("srv-2/app.conf"), "other srv data");
+}
@@ -0,0 +1,61 @@
+use crate::common::*;
+use std::fs;
+
+#[test]
+fn test_stats_counts_unique_content_once() {
+    let env = TestEnvironment::new();
+    let db = env.db_path.to_str().unwrap();
+    fs::create_dir_all(env.test_data_dir.join("subdir")).unwrap();
+    fs::write(env.test_data_dir.join("a.txt"), "Hello World").unwrap();
+    fs::write(env.test_data_dir.join("copy.txt"), "Hello World").unwrap();
+    fs::write(env.test_data_dir.join("subdir/b.txt"), "Nested file").unwrap();
+    run_backuptool(&["snapshot", "--target-directory", env.test_data_dir.to_str().unwrap(), "--database", db]);
+    fs::write(env.test_data_dir.join("subdir/c.txt"), "New content").unwrap();
+    run_backuptool(&["snapshot", "--target-directory", env.test_data_dir.to_str().unwrap(), "--database", db]);
+
+    let output = run_backuptool(&["stats", "--json", "--database", db]);
+    assert!(output.status.success(), "Stats failed: {}", String::from_utf8_lossy(&output.stderr));
+    let stats: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    assert_eq!(stats["snapshots"], 2);
+    assert_eq!(stats["files"], 7);
+    assert_eq!(stats["distinct_contents"], 3);
+    assert_eq!(stats["logical_bytes"], 77);
+    assert_eq!(stats["unique_bytes"], 33);
+    assert_eq!(stats["dedup_ratio"], 77.0 / 33.0);
+
+    assert_eq!(stats["snapshot_stats"][0]["parent"], serde_json::Value::Null);
+    assert_eq!(stats["snapshot_stats"][0]["added_bytes"], 22);
+    assert_eq!(stats["snapshot_stats"][1]["parent"], 1);
+    assert_eq!(stats["snapshot_stats"][1]["added_bytes"], 11);
+
+    assert_eq!(stats["top_directories"], serde_json::json!([
+        {"path": "subdir", "unique_bytes": 22},
+        {"path": ".", "unique_bytes": 11},
+    ]));
+
+    let output = run_backuptool(&["stats", "--database", db]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Deduplication ratio: 2.33"));
+}
+
+#[test]
+fn test_stats_ignore_forgotten_snapshots() {
+    let env = TestEnvironment::new();
+    let db = env.db_path.to_str().unwrap();
+    fs::write(env.test_data_dir.join("a.txt"), "Hello World").unwrap();
+    fs::write(env.test_data_dir.join("copy.txt"), "Hello World").unwrap();
+    run_backuptool(&["snapshot", "--target-directory", env.test_data_dir.to_str().unwrap(), "--database", db]);
+    fs::write(env.test_data_dir.join("b.txt"), "New content").unwrap();
+    run_backuptool(&["snapshot", "--target-directory", env.test_data_dir.to_str().unwrap(), "--database", db]);
+    run_backuptool(&["forget", "--snapshot", "2", "--database", db]);
+
+    // The content only the forgotten snapshot refers to is still stored but no longer counted
+    let output = run_backuptool(&["stats", "--json", "--database", db]);
+    let stats: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(stats["snapshots"], 1);
+    assert_eq!(stats["forgotten_snapshots"], 1);
+    assert_eq!(stats["logical_bytes"], 22);
+    assert_eq!(stats["unique_bytes"], 11);
+    assert_eq!(stats["dedup_ratio"], 2.0);
+    assert!(stats["stored_bytes"].as_u64().unwrap() >= 22);
+}
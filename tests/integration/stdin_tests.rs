@@ -0,0 +1,68 @@
+use crate::common::*;
+use serde_json::Value;
+
+fn snapshot_stdin(env: &TestEnvironment, input: &[u8]) -> Value {
+    let output = run_backuptool_with_stdin(&[
+        "snapshot", "--stdin", "--stdin-filename", "dumps/db.sql", "--json",
+        "--database", env.db_path.to_str().unwrap()
+    ], input);
+    assert!(output.status.success(), "Snapshot failed: {}", String::from_utf8_lossy(&output.stderr));
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+fn cat(env: &TestEnvironment, snapshot: &str) -> Vec<u8> {
+    let output = run_backuptool(&[
+        "cat", "--snapshot", snapshot, "dumps/db.sql",
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+    assert!(output.status.success(), "Cat failed: {}", String::from_utf8_lossy(&output.stderr));
+    output.stdout
+}
+
+#[test]
+fn test_snapshot_stdin_roundtrip() {
+    let env = TestEnvironment::new();
+    let dump = pseudo_random(3 * 1024 * 1024, 1);
+
+    let summary = snapshot_stdin(&env, &dump);
+    assert_eq!(summary["files_processed"], 1);
+    assert_eq!(summary["total_size"], dump.len());
+
+    assert!(cat(&env, "latest") == dump, "cat output differs from the input");
+
+    let restore_dir = env.restore_dir("stdin");
+    let output = run_backuptool(&[
+        "restore",
+        "--snapshot-number", "1",
+        "--output-directory", restore_dir.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+    assert!(output.status.success());
+    verify_binary_content(&restore_dir.join("dumps/db.sql"), &dump);
+
+    let output = run_backuptool_with_stdin(&[
+        "snapshot", "--stdin", "--stdin-filename", "../escape.sql",
+        "--database", env.db_path.to_str().unwrap()
+    ], b"data");
+    assert_eq!(output.status.code(), Some(2), "Filenames outside the snapshot must be rejected");
+}
+
+#[test]
+fn test_snapshot_stdin_dedupes_across_runs() {
+    let env = TestEnvironment::new();
+    let night_one = pseudo_random(12 * 1024 * 1024, 2);
+    let mut night_two = b"-- dumped on night two\n".to_vec();
+    night_two.extend_from_slice(&night_one);
+
+    let first = snapshot_stdin(&env, &night_one);
+    assert_eq!(first["bytes_added"], night_one.len());
+
+    let second = snapshot_stdin(&env, &night_two);
+    let added = second["bytes_added"].as_u64().unwrap();
+    assert!(added < night_one.len() as u64 / 2, "Only the changed chunks should be stored, added {} bytes", added);
+
+    // The chunks shared with the pruned first night must survive the orphan cleanup
+    let output = run_backuptool(&["prune", "--snapshot", "1", "--database", env.db_path.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert!(cat(&env, "latest") == night_two, "cat output differs from the second night's input");
+}
@@ -0,0 +1,129 @@
+use crate::common::*;
+use serde_json::Value;
+
+fn snapshot_with_tags(env: &TestEnvironment, tags: &[&str]) {
+    let mut args = vec![
+        "snapshot",
+        "--target-directory", env.test_data_dir.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap(),
+    ];
+    for tag in tags {
+        args.extend(["--tag", tag]);
+    }
+    let output = run_backuptool(&args);
+    assert!(output.status.success(), "Snapshot failed: {}", String::from_utf8_lossy(&output.stderr));
+}
+
+fn listed_ids(env: &TestEnvironment, tags: &[&str]) -> Vec<u64> {
+    let mut args = vec!["list", "--json", "--database", env.db_path.to_str().unwrap()];
+    for tag in tags {
+        args.extend(["--tag", tag]);
+    }
+    let output = run_backuptool(&args);
+    assert!(output.status.success());
+    let snapshots: Value = serde_json::from_slice(&output.stdout).unwrap();
+    snapshots.as_array().unwrap().iter().map(|s| s["id"].as_u64().unwrap()).collect()
+}
+
+#[test]
+fn test_snapshot_tags_and_message_are_listed() {
+    let env = TestEnvironment::new();
+    create_test_files(&env.test_data_dir).unwrap();
+
+    let output = run_backuptool(&[
+        "snapshot",
+        "--target-directory", env.test_data_dir.to_str().unwrap(),
+        "--tag", "pre-upgrade",
+        "--tag", "manual",
+        "--message", "Before upgrading to v2",
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+    assert!(output.status.success());
+    snapshot_with_tags(&env, &["nightly"]);
+
+    let output = run_backuptool(&["list", "--json", "--database", env.db_path.to_str().unwrap()]);
+    let snapshots: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(snapshots[0]["tags"], serde_json::json!(["manual", "pre-upgrade"]));
+    assert_eq!(snapshots[0]["message"], "Before upgrading to v2");
+    assert_eq!(snapshots[1]["tags"], serde_json::json!(["nightly"]));
+
+    assert_eq!(listed_ids(&env, &["pre-upgrade"]), vec![1]);
+    assert_eq!(listed_ids(&env, &["nightly"]), vec![2]);
+    assert!(listed_ids(&env, &["nightly", "manual"]).is_empty());
+
+    let output = run_backuptool(&["list", "--database", env.db_path.to_str().unwrap()]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("manual,pre-upgrade"));
+}
+
+#[test]
+fn test_tag_add_and_remove() {
+    let env = TestEnvironment::new();
+    create_test_files(&env.test_data_dir).unwrap();
+    snapshot_with_tags(&env, &[]);
+
+    let output = run_backuptool(&[
+        "tag", "add", "--snapshot", "1", "keep", "audit",
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Snapshot 1 tags: audit, keep"));
+
+    let output = run_backuptool(&[
+        "tag", "remove", "--snapshot", "1", "audit",
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+    assert!(output.status.success());
+    assert_eq!(listed_ids(&env, &["keep"]), vec![1]);
+    assert!(listed_ids(&env, &["audit"]).is_empty());
+
+    let output = run_backuptool(&[
+        "tag", "add", "--snapshot", "7", "keep",
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+    assert_eq!(output.status.code(), Some(3));
+
+    let output = run_backuptool(&[
+        "tag", "add", "--snapshot", "1", "two words",
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_prune_keep_last_only_touches_tagged_snapshots() {
+    let env = TestEnvironment::new();
+    create_test_files(&env.test_data_dir).unwrap();
+
+    snapshot_with_tags(&env, &["nightly"]);
+    snapshot_with_tags(&env, &["pre-upgrade"]);
+    snapshot_with_tags(&env, &["nightly"]);
+    snapshot_with_tags(&env, &["nightly"]);
+
+    let output = run_backuptool(&[
+        "prune", "--keep-last", "1", "--tag", "nightly",
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+    assert!(output.status.success(), "Prune failed: {}", String::from_utf8_lossy(&output.stderr));
+
+    assert_eq!(listed_ids(&env, &[]), vec![2, 4]);
+}
+
+#[test]
+fn test_restore_latest_snapshot_with_tag() {
+    let env = TestEnvironment::new();
+    create_test_files(&env.test_data_dir).unwrap();
+
+    snapshot_with_tags(&env, &["pre-upgrade"]);
+    std::fs::write(env.test_data_dir.join("file1.txt"), "Upgraded").unwrap();
+    snapshot_with_tags(&env, &["nightly"]);
+
+    let restore_dir = env.restore_dir("tagged");
+    let output = run_backuptool(&[
+        "restore",
+        "--tag", "pre-upgrade",
+        "--output-directory", restore_dir.to_str().unwrap(),
+        "--database", env.db_path.to_str().unwrap()
+    ]);
+    assert!(output.status.success(), "Restore failed: {}", String::from_utf8_lossy(&output.stderr));
+    verify_file_content(&restore_dir.join("file1.txt"), "Hello World");
+}